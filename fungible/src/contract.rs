@@ -8,10 +8,11 @@ mod state;
 use std::str::FromStr;
 
 use fungible::{
-    Account, FungibleResponse, FungibleTokenAbi, InitialState, Message, Operation, Parameters,
+    Account, FungibleEvent, FungibleResponse, FungibleTokenAbi, InitialState, Message, Operation,
+    Parameters, TransferDirection, TransferRecord, FUNGIBLE_STREAM_NAME, MAX_MEMO_LEN,
 };
 use linera_sdk::{
-    linera_base_types::{AccountOwner, Amount, WithContractAbi},
+    linera_base_types::{AccountOwner, Amount, StreamName, WithContractAbi},
     views::{RootView, View},
     Contract, ContractRuntime,
 };
@@ -33,7 +34,7 @@ impl Contract for FungibleTokenContract {
     type Message = Message;
     type Parameters = Parameters;
     type InstantiationArgument = InitialState;
-    type EventValue = ();
+    type EventValue = FungibleEvent;
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
         let state = FungibleTokenState::load(runtime.root_view_storage_context())
@@ -44,16 +45,33 @@ impl Contract for FungibleTokenContract {
 
     async fn instantiate(&mut self, mut state: Self::InstantiationArgument) {
         // Validate that the application parameters were configured correctly.
-        let _ = self.runtime.application_parameters();
+        let params = self.runtime.application_parameters();
+        assert!(
+            !params.ticker_symbol.is_empty() && !params.name.is_empty(),
+            "Token needs a ticker symbol and a name"
+        );
+        assert!(
+            params.decimals <= Amount::DECIMAL_PLACES,
+            "Token can't have more than {} decimals",
+            Amount::DECIMAL_PLACES
+        );
 
-        // If initial accounts are empty, creator gets 1M tokens to act like a faucet.
-        if state.accounts.is_empty() {
+        self.state.admin.set(self.runtime.authenticated_signer());
+
+        // If initial accounts are empty, creator gets 1M tokens to act like a faucet. Tokens with
+        // a capped or minted supply are only ever issued on purpose.
+        if state.accounts.is_empty() && params.max_supply.is_none() && !params.mintable {
             if let Some(owner) = self.runtime.authenticated_signer() {
                 state
                     .accounts
                     .insert(owner, Amount::from_str("1000000").unwrap());
             }
         }
+        let initial_supply = state
+            .accounts
+            .values()
+            .fold(Amount::ZERO, |total, amount| total.saturating_add(*amount));
+        self.state.issue(initial_supply, params.max_supply);
         self.state.initialize_accounts(state).await;
     }
 
@@ -69,6 +87,12 @@ impl Contract for FungibleTokenContract {
                 FungibleResponse::TickerSymbol(params.ticker_symbol)
             }
 
+            Operation::TotalSupply => FungibleResponse::TotalSupply(*self.state.total_supply.get()),
+
+            Operation::Metadata => {
+                FungibleResponse::Metadata(self.runtime.application_parameters())
+            }
+
             // ANCHOR: execute_operation_transfer
             Operation::Transfer {
                 owner,
@@ -79,11 +103,31 @@ impl Contract for FungibleTokenContract {
                     .check_account_permission(owner)
                     .expect("Permission for Transfer operation");
                 self.state.debit(owner, amount).await;
-                self.finish_transfer_to_account(amount, target_account, owner)
+                self.finish_transfer_to_account(amount, target_account, owner, None)
                     .await;
                 FungibleResponse::Ok
             }
             // ANCHOR_END: execute_operation_transfer
+            Operation::TransferWithMemo {
+                owner,
+                amount,
+                target_account,
+                memo,
+            } => {
+                self.runtime
+                    .check_account_permission(owner)
+                    .expect("Permission for TransferWithMemo operation");
+                if let Some(memo) = &memo {
+                    assert!(
+                        memo.len() <= MAX_MEMO_LEN,
+                        "Memo is longer than {MAX_MEMO_LEN} bytes"
+                    );
+                }
+                self.state.debit(owner, amount).await;
+                self.finish_transfer_to_account(amount, target_account, owner, memo)
+                    .await;
+                FungibleResponse::Ok
+            }
             Operation::Claim {
                 source_account,
                 amount,
@@ -95,6 +139,67 @@ impl Contract for FungibleTokenContract {
                 self.claim(source_account, amount, target_account).await;
                 FungibleResponse::Ok
             }
+
+            Operation::SetMinter { minter } => {
+                let signer = self.runtime.authenticated_signer();
+                assert!(
+                    signer.is_some() && signer == *self.state.admin.get(),
+                    "Only the admin can set the minter"
+                );
+                assert!(
+                    self.runtime.application_parameters().mintable,
+                    "Token is not mintable"
+                );
+                self.state.minter.set(Some(minter));
+                FungibleResponse::Ok
+            }
+
+            Operation::Mint {
+                target_account,
+                amount,
+            } => {
+                let minter = self.check_minter();
+                let max_supply = self.runtime.application_parameters().max_supply;
+                self.state.issue(amount, max_supply);
+                // nothing is debited, so only the target records the tokens
+                self.credit_account(amount, target_account, minter, None)
+                    .await;
+                FungibleResponse::Ok
+            }
+
+            Operation::Approve { spender, amount } => {
+                let owner = self
+                    .runtime
+                    .authenticated_signer()
+                    .expect("Approve operation must be signed");
+                self.state.approve(owner, spender, amount);
+                FungibleResponse::Ok
+            }
+
+            Operation::TransferFrom {
+                owner,
+                spender,
+                amount,
+                target_account,
+            } => {
+                self.runtime
+                    .check_account_permission(spender)
+                    .expect("Permission for TransferFrom operation");
+                self.state.spend_allowance(owner, spender, amount).await;
+                self.state.debit(owner, amount).await;
+                self.finish_transfer_to_account(amount, target_account, owner, None)
+                    .await;
+                FungibleResponse::Ok
+            }
+
+            Operation::Burn { owner, amount } => {
+                self.runtime
+                    .check_account_permission(owner)
+                    .expect("Permission for Burn operation");
+                self.state.debit(owner, amount).await;
+                self.state.retire(amount);
+                FungibleResponse::Ok
+            }
         }
     }
 
@@ -105,13 +210,45 @@ impl Contract for FungibleTokenContract {
                 amount,
                 target,
                 source,
+                memo,
             } => {
                 let is_bouncing = self
                     .runtime
                     .message_is_bouncing()
                     .expect("Delivery status is available when executing a message");
-                let receiver = if is_bouncing { source } else { target };
+                let origin_chain_id = self
+                    .runtime
+                    .message_origin_chain_id()
+                    .expect("Origin chain is available when executing a message");
+                let (receiver, counterparty) = if is_bouncing {
+                    (source, target)
+                } else {
+                    (target, source)
+                };
                 self.state.credit(receiver, amount).await;
+                let counterparty_account = Account {
+                    chain_id: origin_chain_id,
+                    owner: counterparty,
+                };
+                self.record_transfer(
+                    receiver,
+                    TransferDirection::Incoming,
+                    amount,
+                    counterparty_account,
+                    is_bouncing,
+                    memo.clone(),
+                )
+                .await;
+                self.runtime.emit(
+                    StreamName::from(FUNGIBLE_STREAM_NAME),
+                    &FungibleEvent::Received {
+                        source_account: counterparty_account,
+                        target: receiver,
+                        amount,
+                        bounced: is_bouncing,
+                        memo,
+                    },
+                );
             }
             // ANCHOR_END: execute_message_credit
             Message::Withdraw {
@@ -123,7 +260,7 @@ impl Contract for FungibleTokenContract {
                     .check_account_permission(owner)
                     .expect("Permission for Withdraw message");
                 self.state.debit(owner, amount).await;
-                self.finish_transfer_to_account(amount, target_account, owner)
+                self.finish_transfer_to_account(amount, target_account, owner, None)
                     .await;
             }
         }
@@ -135,10 +272,23 @@ impl Contract for FungibleTokenContract {
 }
 
 impl FungibleTokenContract {
+    /// Checks that the minter is calling, and returns it.
+    fn check_minter(&mut self) -> AccountOwner {
+        let minter = self
+            .state
+            .minter
+            .get()
+            .expect("No minter is set on this chain");
+        self.runtime
+            .check_account_permission(minter)
+            .expect("Permission for minting");
+        minter
+    }
+
     async fn claim(&mut self, source_account: Account, amount: Amount, target_account: Account) {
         if source_account.chain_id == self.runtime.chain_id() {
             self.state.debit(source_account.owner, amount).await;
-            self.finish_transfer_to_account(amount, target_account, source_account.owner)
+            self.finish_transfer_to_account(amount, target_account, source_account.owner, None)
                 .await;
         } else {
             let message = Message::Withdraw {
@@ -160,14 +310,62 @@ impl FungibleTokenContract {
         amount: Amount,
         target_account: Account,
         source: AccountOwner,
+        memo: Option<Vec<u8>>,
+    ) {
+        self.record_transfer(
+            source,
+            TransferDirection::Outgoing,
+            amount,
+            target_account,
+            false,
+            memo.clone(),
+        )
+        .await;
+        self.runtime.emit(
+            StreamName::from(FUNGIBLE_STREAM_NAME),
+            &FungibleEvent::Transferred {
+                source,
+                target_account,
+                amount,
+                memo: memo.clone(),
+            },
+        );
+        self.credit_account(amount, target_account, source, memo)
+            .await;
+    }
+    // ANCHOR_END: finish_transfer_to_account
+
+    /// Credits `amount` tokens from `source` to `target_account`, recording only the incoming
+    /// side of the transfer.
+    async fn credit_account(
+        &mut self,
+        amount: Amount,
+        target_account: Account,
+        source: AccountOwner,
+        memo: Option<Vec<u8>>,
     ) {
-        if target_account.chain_id == self.runtime.chain_id() {
+        let chain_id = self.runtime.chain_id();
+        if target_account.chain_id == chain_id {
             self.state.credit(target_account.owner, amount).await;
+            let source_account = Account {
+                chain_id,
+                owner: source,
+            };
+            self.record_transfer(
+                target_account.owner,
+                TransferDirection::Incoming,
+                amount,
+                source_account,
+                false,
+                memo,
+            )
+            .await;
         } else {
             let message = Message::Credit {
                 target: target_account.owner,
                 amount,
                 source,
+                memo,
             };
             self.runtime
                 .prepare_message(message)
@@ -176,5 +374,26 @@ impl FungibleTokenContract {
                 .send_to(target_account.chain_id);
         }
     }
-    // ANCHOR_END: finish_transfer_to_account
+
+    /// Adds a transfer executed now to `owner`'s history.
+    async fn record_transfer(
+        &mut self,
+        owner: AccountOwner,
+        direction: TransferDirection,
+        amount: Amount,
+        counterparty: Account,
+        bounced: bool,
+        memo: Option<Vec<u8>>,
+    ) {
+        let record = TransferRecord {
+            direction,
+            amount,
+            counterparty: counterparty.owner,
+            chain_id: counterparty.chain_id,
+            timestamp: self.runtime.system_time(),
+            bounced,
+            memo,
+        };
+        self.state.record_transfer(owner, record).await;
+    }
 }
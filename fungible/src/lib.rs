@@ -3,8 +3,12 @@
 
 /* ABI of the Fungible Token Example Application */
 
+use async_graphql::{Enum, InputObject, Request, Response, SimpleObject};
 pub use linera_sdk::abis::fungible::*;
-use linera_sdk::linera_base_types::{AccountOwner, Amount};
+use linera_sdk::{
+    graphql::GraphQLMutationRoot,
+    linera_base_types::{AccountOwner, Amount, ChainId, ContractAbi, ServiceAbi, Timestamp},
+};
 use serde::{Deserialize, Serialize};
 #[cfg(all(any(test, feature = "test"), not(target_arch = "wasm32")))]
 use {
@@ -16,6 +20,242 @@ use {
     },
 };
 
+/// The ABI of the fungible token, extending the SDK's with minting, allowances and metadata.
+pub struct FungibleTokenAbi;
+
+impl ContractAbi for FungibleTokenAbi {
+    type Operation = Operation;
+    type Response = FungibleResponse;
+}
+
+impl ServiceAbi for FungibleTokenAbi {
+    type Query = Request;
+    type QueryResponse = Response;
+}
+
+/// The token metadata, fixed when the application is created.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, SimpleObject)]
+pub struct Parameters {
+    /// Short symbol of the token, e.g. "LIN"
+    pub ticker_symbol: String,
+    /// Display name of the token
+    pub name: String,
+    /// Number of decimals frontends should display, at most `Amount::DECIMAL_PLACES`
+    pub decimals: u8,
+    /// Hard cap on the tokens ever created, unlimited when `None`
+    pub max_supply: Option<Amount>,
+    /// Whether a minter can be set to create tokens after instantiation
+    pub mintable: bool,
+    /// URI of the token icon
+    pub icon_uri: Option<String>,
+}
+
+impl Parameters {
+    /// Creates metadata for `ticker_symbol`, using it as the name too. The token can't be
+    /// minted after instantiation unless `mintable` is set.
+    pub fn new(ticker_symbol: &str) -> Self {
+        Parameters {
+            ticker_symbol: ticker_symbol.to_string(),
+            name: ticker_symbol.to_string(),
+            decimals: Amount::DECIMAL_PLACES,
+            max_supply: None,
+            mintable: false,
+            icon_uri: None,
+        }
+    }
+}
+
+/// An operation.
+#[derive(Debug, Deserialize, Serialize, GraphQLMutationRoot)]
+pub enum Operation {
+    /// Requests an account balance.
+    Balance {
+        /// Owner to query the balance for
+        owner: AccountOwner,
+    },
+    /// Requests this fungible token's ticker symbol.
+    TickerSymbol,
+    /// Transfers tokens from a (locally owned) account to a (possibly remote) account.
+    Transfer {
+        /// Owner to transfer from
+        owner: AccountOwner,
+        /// Amount to be transferred
+        amount: Amount,
+        /// Target account to transfer the amount to
+        target_account: Account,
+    },
+    /// Same as `Transfer` but the source account may be remote. Depending on its
+    /// configuration, the target chain may take time or refuse to process
+    /// the message.
+    Claim {
+        /// Source account to claim amount from
+        source_account: Account,
+        /// Amount to be claimed
+        amount: Amount,
+        /// Target account to claim the amount into
+        target_account: Account,
+    },
+    /// Sets the account allowed to mint tokens on this chain. Only the admin, i.e. the
+    /// signer that created the application, can call it.
+    SetMinter {
+        /// The new minter, usually an application
+        minter: AccountOwner,
+    },
+    /// Creates new tokens. Only the minter can call it.
+    Mint {
+        /// Target account to credit the new tokens to
+        target_account: Account,
+        /// Amount to be minted
+        amount: Amount,
+    },
+    /// Allows `spender` to transfer up to `amount` tokens out of the signer's account on this
+    /// chain, replacing any previous allowance.
+    Approve {
+        /// Account allowed to spend the tokens
+        spender: AccountOwner,
+        /// Amount that can be spent
+        amount: Amount,
+    },
+    /// Transfers tokens out of a (locally owned) account that has given `spender` an allowance.
+    TransferFrom {
+        /// Owner to transfer from
+        owner: AccountOwner,
+        /// Spender using its allowance
+        spender: AccountOwner,
+        /// Amount to be transferred
+        amount: Amount,
+        /// Target account to transfer the amount to
+        target_account: Account,
+    },
+    /// Destroys tokens from a (locally owned) account, reducing the total supply.
+    Burn {
+        /// Owner to burn from
+        owner: AccountOwner,
+        /// Amount to be burned
+        amount: Amount,
+    },
+    /// Requests the amount of tokens held in accounts on this chain.
+    TotalSupply,
+    /// Requests this fungible token's metadata.
+    Metadata,
+    /// Same as `Transfer`, with a memo of at most `MAX_MEMO_LEN` bytes carried to the target
+    /// chain and recorded in both accounts' histories, e.g. to reference a request.
+    TransferWithMemo {
+        /// Owner to transfer from
+        owner: AccountOwner,
+        /// Amount to be transferred
+        amount: Amount,
+        /// Target account to transfer the amount to
+        target_account: Account,
+        /// Reference attached to the transfer
+        memo: Option<Vec<u8>>,
+    },
+}
+
+/// An owner and a spender they have given an allowance to.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    SimpleObject,
+    InputObject,
+)]
+#[graphql(input_name = "OwnerSpenderInput")]
+pub struct OwnerSpender {
+    /// Owner of the tokens
+    pub owner: AccountOwner,
+    /// Account allowed to spend them
+    pub spender: AccountOwner,
+}
+
+/// An account owner and their balance on a chain.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, SimpleObject)]
+pub struct Holder {
+    /// Owner of the account
+    pub owner: AccountOwner,
+    /// Balance of the account
+    pub balance: Amount,
+}
+
+/// A page of holders, ordered by owner.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct HolderPage {
+    /// Holders on this page
+    pub holders: Vec<Holder>,
+    /// Cursor to pass as `after` to get the next page, `None` on the last page
+    pub next_cursor: Option<AccountOwner>,
+}
+
+/// Whether a transfer moved tokens into or out of an account.
+#[derive(Clone, Copy, Debug, Deserialize, Enum, Eq, PartialEq, Serialize)]
+pub enum TransferDirection {
+    Incoming,
+    Outgoing,
+}
+
+/// A transfer in an account's history.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, SimpleObject)]
+pub struct TransferRecord {
+    /// Whether the tokens came into or left the account
+    pub direction: TransferDirection,
+    /// Amount transferred
+    pub amount: Amount,
+    /// The other account: the target of an outgoing transfer, the source of an incoming one
+    pub counterparty: AccountOwner,
+    /// Chain of the counterparty
+    pub chain_id: ChainId,
+    /// When the transfer was executed on this chain
+    pub timestamp: Timestamp,
+    /// Whether these are tokens of an outgoing transfer that bounced back
+    pub bounced: bool,
+    /// Reference attached to the transfer
+    pub memo: Option<Vec<u8>>,
+}
+
+/// The longest memo a transfer can carry, in bytes.
+pub const MAX_MEMO_LEN: usize = 64;
+
+/// Name of the stream the fungible token emits its [`FungibleEvent`]s on.
+pub const FUNGIBLE_STREAM_NAME: &[u8] = b"fungible_events";
+
+/// A change to the fungible token, emitted for indexers.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum FungibleEvent {
+    /// Tokens left `source`'s account on the emitting chain for `target_account`.
+    Transferred {
+        source: AccountOwner,
+        target_account: Account,
+        amount: Amount,
+        memo: Option<Vec<u8>>,
+    },
+    /// Tokens sent from `source_account` on another chain were credited to `target`'s account
+    /// on the emitting chain, or returned to the sender if `bounced`.
+    Received {
+        source_account: Account,
+        target: AccountOwner,
+        amount: Amount,
+        bounced: bool,
+        memo: Option<Vec<u8>>,
+    },
+}
+
+/// A response to an operation.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub enum FungibleResponse {
+    #[default]
+    Ok,
+    Balance(Amount),
+    TickerSymbol(String),
+    TotalSupply(Amount),
+    Metadata(Parameters),
+}
+
 /// A message.
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Message {
@@ -28,6 +268,8 @@ pub enum Message {
         amount: Amount,
         /// Source account to remove amount from
         source: AccountOwner,
+        /// Reference attached to the transfer
+        memo: Option<Vec<u8>>,
     },
 
     /// Withdraws from the given account and starts a transfer to the target account.
@@ -134,3 +376,20 @@ pub async fn query_account(
             .expect("Account balance cannot be parsed as a number"),
     )
 }
+
+/// Queries the amount of tokens held in accounts on a specific `chain`.
+#[cfg(all(any(test, feature = "test"), not(target_arch = "wasm32")))]
+pub async fn query_total_supply(
+    application_id: ApplicationId<FungibleTokenAbi>,
+    chain: &ActiveChain,
+) -> Amount {
+    let QueryOutcome { response, .. } = chain
+        .graphql_query(application_id, "query { totalSupply }")
+        .await;
+
+    response["totalSupply"]
+        .as_str()
+        .expect("Missing total supply")
+        .parse()
+        .expect("Total supply cannot be parsed as a number")
+}
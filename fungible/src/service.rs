@@ -8,7 +8,7 @@ mod state;
 use std::sync::Arc;
 
 use async_graphql::{EmptySubscription, Object, Request, Response, Schema};
-use fungible::{Operation, Parameters};
+use fungible::{Holder, HolderPage, Operation, OwnerSpender, Parameters, TransferRecord};
 use linera_sdk::{
     graphql::GraphQLMutationRoot,
     linera_base_types::{AccountOwner, Amount, WithServiceAbi},
@@ -18,6 +18,9 @@ use linera_sdk::{
 
 use self::state::FungibleTokenState;
 
+/// The most holders or transfers returned by a single query.
+const MAX_PAGE_SIZE: usize = 100;
+
 #[derive(Clone)]
 pub struct FungibleTokenService {
     state: Arc<FungibleTokenState>,
@@ -60,7 +63,107 @@ impl FungibleTokenService {
         &self.state.accounts
     }
 
+    /// Returns up to `limit` holders on this chain, ordered by owner and starting after the
+    /// `after` cursor.
+    async fn holders(
+        &self,
+        after: Option<AccountOwner>,
+        limit: Option<u32>,
+    ) -> Result<HolderPage, async_graphql::Error> {
+        let limit = limit.map_or(MAX_PAGE_SIZE, |limit| (limit as usize).min(MAX_PAGE_SIZE));
+        let mut holders = Vec::new();
+        let mut has_more = false;
+        self.state
+            .accounts
+            .for_each_index_value_while(|owner, balance| {
+                if after.is_some_and(|cursor| owner <= cursor) {
+                    return Ok(true);
+                }
+                if holders.len() == limit {
+                    has_more = true;
+                    return Ok(false);
+                }
+                holders.push(Holder {
+                    owner,
+                    balance: *balance,
+                });
+                Ok(true)
+            })
+            .await?;
+        let next_cursor = if has_more {
+            holders.last().map(|holder| holder.owner)
+        } else {
+            None
+        };
+        Ok(HolderPage {
+            holders,
+            next_cursor,
+        })
+    }
+
+    /// Returns the `limit` holders on this chain with the largest balances, largest first.
+    async fn top_holders(&self, limit: u32) -> Result<Vec<Holder>, async_graphql::Error> {
+        let mut holders = Vec::new();
+        self.state
+            .accounts
+            .for_each_index_value(|owner, balance| {
+                holders.push(Holder {
+                    owner,
+                    balance: *balance,
+                });
+                Ok(())
+            })
+            .await?;
+        holders.sort_by(|a, b| b.balance.cmp(&a.balance).then(a.owner.cmp(&b.owner)));
+        holders.truncate((limit as usize).min(MAX_PAGE_SIZE));
+        Ok(holders)
+    }
+
+    /// The number of accounts holding tokens on this chain.
+    async fn holder_count(&self) -> Result<u64, async_graphql::Error> {
+        Ok(self.state.accounts.count().await? as u64)
+    }
+
+    /// Returns up to `limit` of `owner`'s transfers on this chain, newest first, skipping the
+    /// `offset` most recent ones.
+    async fn history(
+        &self,
+        owner: AccountOwner,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<Vec<TransferRecord>, async_graphql::Error> {
+        let history = self.state.history.get(&owner).await?.unwrap_or_default();
+        let limit = limit.map_or(MAX_PAGE_SIZE, |limit| (limit as usize).min(MAX_PAGE_SIZE));
+        Ok(history
+            .into_iter()
+            .rev()
+            .skip(offset.unwrap_or(0) as usize)
+            .take(limit)
+            .collect())
+    }
+
+    async fn allowances(&self) -> &MapView<OwnerSpender, Amount> {
+        &self.state.allowances
+    }
+
+    async fn allowance(&self, owner: AccountOwner, spender: AccountOwner) -> Amount {
+        self.state.allowance(owner, spender).await
+    }
+
+    async fn minter(&self) -> Option<AccountOwner> {
+        *self.state.minter.get()
+    }
+
     async fn ticker_symbol(&self) -> Result<String, async_graphql::Error> {
         Ok(self.runtime.application_parameters().ticker_symbol)
     }
+
+    async fn metadata(&self) -> Parameters {
+        self.runtime.application_parameters()
+    }
+
+    /// The amount of tokens held in accounts on this chain.
+    async fn total_supply(&self) -> Amount {
+        *self.state.total_supply.get()
+    }
 }
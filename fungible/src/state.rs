@@ -1,17 +1,35 @@
 // Copyright (c) Zefchain Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use fungible::InitialState;
+use fungible::{InitialState, OwnerSpender, TransferRecord};
 use linera_sdk::{
     linera_base_types::{AccountOwner, Amount},
-    views::{linera_views, MapView, RootView, ViewStorageContext},
+    views::{linera_views, MapView, RegisterView, RootView, ViewStorageContext},
 };
 use log::warn;
+
+/// The most transfers kept in each owner's history.
+const MAX_HISTORY_LEN: usize = 100;
+
 /// The application state.
 #[derive(RootView)]
 #[view(context = "ViewStorageContext")]
 pub struct FungibleTokenState {
     pub accounts: MapView<AccountOwner, Amount>,
+    /// The signer that created the application, allowed to set the minter.
+    pub admin: RegisterView<Option<AccountOwner>>,
+    /// The account allowed to mint tokens on this chain.
+    pub minter: RegisterView<Option<AccountOwner>>,
+    /// The amount of tokens held in accounts on this chain.
+    pub total_supply: RegisterView<Amount>,
+    /// The amount of tokens created on this chain, at instantiation or by minting, less those
+    /// burned on it. Only meaningful on the creator chain, where the minter is set.
+    pub issued: RegisterView<Amount>,
+    /// The amounts spenders may still transfer out of owners' accounts on this chain.
+    pub allowances: MapView<OwnerSpender, Amount>,
+    /// The most recent transfers into and out of each owner's account on this chain, oldest
+    /// first.
+    pub history: MapView<AccountOwner, Vec<TransferRecord>>,
 }
 
 #[allow(dead_code)]
@@ -21,6 +39,7 @@ impl FungibleTokenState {
         for (k, v) in state.accounts {
             if v != Amount::ZERO {
                 self.accounts.insert(&k, v).expect("Error in insert statement");
+                self.total_supply.get_mut().saturating_add_assign(v);
             }
         }
     }
@@ -43,6 +62,7 @@ impl FungibleTokenState {
         let mut balance = self.balance_or_default(&account).await;
         balance.saturating_add_assign(amount);
         self.accounts.insert(&account, balance).expect("Failed insert statement");
+        self.total_supply.get_mut().saturating_add_assign(amount);
     }
 
     /// Tries to debit the requested `amount` from an `account`.
@@ -60,5 +80,59 @@ impl FungibleTokenState {
         } else {
             self.accounts.insert(&account, balance).expect("Failed insertion operation");
         }
+        self.total_supply.get_mut().try_sub_assign(amount).expect("Total supply underflow");
+    }
+
+    /// Records `amount` newly created tokens, which must keep the issued tokens within
+    /// `max_supply`.
+    pub(crate) fn issue(&mut self, amount: Amount, max_supply: Option<Amount>) {
+        let issued = self.issued.get().saturating_add(amount);
+        if let Some(max_supply) = max_supply {
+            assert!(issued <= max_supply, "Issued tokens would exceed the maximum supply of {max_supply}");
+        }
+        self.issued.set(issued);
+    }
+
+    /// Records `amount` burned tokens.
+    pub(crate) fn retire(&mut self, amount: Amount) {
+        let issued = self.issued.get().saturating_sub(amount);
+        self.issued.set(issued);
+    }
+
+    /// Adds `record` to `owner`'s history, dropping the oldest transfer once it is full.
+    pub(crate) async fn record_transfer(&mut self, owner: AccountOwner, record: TransferRecord) {
+        let history = self.history.get_mut_or_default(&owner).await.expect("Failure in the retrieval");
+        if history.len() == MAX_HISTORY_LEN {
+            history.remove(0);
+        }
+        history.push(record);
+    }
+
+    /// Returns the amount `spender` may still transfer out of `owner`'s account.
+    pub(crate) async fn allowance(&self, owner: AccountOwner, spender: AccountOwner) -> Amount {
+        self.allowances
+            .get(&OwnerSpender { owner, spender })
+            .await
+            .expect("Failure in the retrieval")
+            .unwrap_or_default()
+    }
+
+    /// Lets `spender` transfer up to `amount` out of `owner`'s account.
+    pub(crate) fn approve(&mut self, owner: AccountOwner, spender: AccountOwner, amount: Amount) {
+        let key = OwnerSpender { owner, spender };
+        if amount == Amount::ZERO {
+            self.allowances.remove(&key).expect("Failed to remove an empty allowance");
+        } else {
+            self.allowances.insert(&key, amount).expect("Failed insert statement");
+        }
+    }
+
+    /// Uses up `amount` of the allowance `owner` gave `spender`.
+    pub(crate) async fn spend_allowance(&mut self, owner: AccountOwner, spender: AccountOwner, amount: Amount) {
+        let mut allowance = self.allowance(owner, spender).await;
+        allowance
+            .try_sub_assign(amount)
+            .unwrap_or_else(|_| panic!("Spender {spender} is not allowed to transfer {amount} from {owner}"));
+        self.approve(owner, spender, allowance);
     }
 }
@@ -5,12 +5,14 @@
 
 #![cfg(not(target_arch = "wasm32"))]
 
+use async_graphql::InputType;
 use fungible::{
     Account, FungibleTokenAbi, InitialState, InitialStateBuilder, Operation, Parameters,
+    MAX_MEMO_LEN,
 };
 use linera_sdk::{
     linera_base_types::{AccountOwner, Amount},
-    test::{Medium, MessageAction, TestValidator},
+    test::{Medium, MessageAction, QueryOutcome, TestValidator},
 };
 
 /// Test transferring tokens across microchains.
@@ -138,3 +140,554 @@ async fn test_bouncing_tokens() {
         Some(initial_amount),
     );
 }
+
+/// Test reading the transfer history.
+///
+/// Creates the application on an `owner_chain` and makes more transfers to another owner on the
+/// same chain than the history keeps. Checks that the oldest transfers are dropped and that the
+/// history can be read page by page, newest first.
+#[tokio::test]
+async fn test_history() {
+    let initial_amount = Amount::from_tokens(10_000);
+    let transfer_count = 105;
+
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut owner_chain = validator.new_chain().await;
+    let owner_account = AccountOwner::from(owner_chain.public_key());
+    let receiver_account = AccountOwner::from(validator.new_chain().await.public_key());
+
+    let initial_state = InitialStateBuilder::default().with_account(owner_account, initial_amount);
+    let params = Parameters::new("HST");
+    let application_id = owner_chain
+        .create_application(module_id, params, initial_state.build(), vec![])
+        .await;
+
+    let target_account = Account {
+        chain_id: owner_chain.id(),
+        owner: receiver_account,
+    };
+    owner_chain
+        .add_block(|block| {
+            for tokens in 1..=transfer_count {
+                block.with_operation(
+                    application_id,
+                    Operation::Transfer {
+                        owner: owner_account,
+                        amount: Amount::from_tokens(tokens),
+                        target_account,
+                    },
+                );
+            }
+        })
+        .await;
+
+    // only the latest 100 transfers are kept
+    let query = format!(
+        "query {{ history(owner: {}) {{ direction amount counterparty }} }}",
+        owner_account.to_value()
+    );
+    let QueryOutcome { response, .. } = owner_chain.graphql_query(application_id, query).await;
+    let history = response["history"].as_array().expect("Missing history");
+    assert_eq!(history.len(), 100);
+    assert_eq!(history[0]["direction"], "OUTGOING");
+    assert_eq!(history[0]["amount"], "105.");
+    assert_eq!(history[0]["counterparty"], receiver_account.to_string());
+    assert_eq!(history[99]["amount"], "6.");
+
+    let query = format!(
+        "query {{ history(owner: {}, offset: 10, limit: 3) {{ amount }} }}",
+        owner_account.to_value()
+    );
+    let QueryOutcome { response, .. } = owner_chain.graphql_query(application_id, query).await;
+    let amounts = response["history"]
+        .as_array()
+        .expect("Missing history")
+        .iter()
+        .map(|record| record["amount"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(amounts, ["95.", "94.", "93."]);
+
+    // the receiver records the incoming side
+    let query = format!(
+        "query {{ history(owner: {}, limit: 1) {{ direction amount counterparty }} }}",
+        receiver_account.to_value()
+    );
+    let QueryOutcome { response, .. } = owner_chain.graphql_query(application_id, query).await;
+    let history = response["history"].as_array().expect("Missing history");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["direction"], "INCOMING");
+    assert_eq!(history[0]["amount"], "105.");
+    assert_eq!(history[0]["counterparty"], owner_account.to_string());
+}
+
+/// Test minting and burning tokens.
+///
+/// Creates the application on a `minter_chain` and makes that chain's owner the minter. Mints some
+/// tokens to a new `receiver_chain`, burns some of the minter's own tokens, and checks that the
+/// balances on each microchain are correct and that only the receiver records the minted tokens.
+#[tokio::test]
+async fn test_mint_and_burn() {
+    let initial_amount = Amount::from_tokens(10);
+    let mint_amount = Amount::from_tokens(6);
+    let burn_amount = Amount::from_tokens(4);
+
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut minter_chain = validator.new_chain().await;
+    let minter_account = AccountOwner::from(minter_chain.public_key());
+
+    let initial_state = InitialStateBuilder::default().with_account(minter_account, initial_amount);
+    let params = Parameters {
+        mintable: true,
+        ..Parameters::new("MNT")
+    };
+    let application_id = minter_chain
+        .create_application(module_id, params, initial_state.build(), vec![])
+        .await;
+
+    let receiver_chain = validator.new_chain().await;
+    let receiver_account = AccountOwner::from(receiver_chain.public_key());
+
+    minter_chain
+        .add_block(|block| {
+            block
+                .with_operation(
+                    application_id,
+                    Operation::SetMinter {
+                        minter: minter_account,
+                    },
+                )
+                .with_operation(
+                    application_id,
+                    Operation::Mint {
+                        target_account: Account {
+                            chain_id: receiver_chain.id(),
+                            owner: receiver_account,
+                        },
+                        amount: mint_amount,
+                    },
+                )
+                .with_operation(
+                    application_id,
+                    Operation::Burn {
+                        owner: minter_account,
+                        amount: burn_amount,
+                    },
+                );
+        })
+        .await;
+
+    assert_eq!(
+        fungible::query_account(application_id, &minter_chain, minter_account).await,
+        Some(initial_amount.saturating_sub(burn_amount)),
+    );
+    assert_eq!(
+        fungible::query_total_supply(application_id, &minter_chain).await,
+        initial_amount.saturating_sub(burn_amount),
+    );
+
+    let query = format!(
+        "query {{ history(owner: {}) {{ direction }} }}",
+        minter_account.to_value()
+    );
+    let QueryOutcome { response, .. } = minter_chain.graphql_query(application_id, query).await;
+    assert_eq!(response["history"].as_array().map(Vec::len), Some(0));
+
+    receiver_chain.handle_received_messages().await;
+
+    assert_eq!(
+        fungible::query_account(application_id, &receiver_chain, receiver_account).await,
+        Some(mint_amount),
+    );
+    assert_eq!(
+        fungible::query_total_supply(application_id, &receiver_chain).await,
+        mint_amount,
+    );
+
+    let query = format!(
+        "query {{ history(owner: {}) {{ direction amount counterparty }} }}",
+        receiver_account.to_value()
+    );
+    let QueryOutcome { response, .. } = receiver_chain.graphql_query(application_id, query).await;
+    let history = response["history"].as_array().expect("Missing history");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["direction"], "INCOMING");
+    assert_eq!(history[0]["counterparty"], minter_account.to_string());
+}
+
+/// Test approving a spender.
+///
+/// Creates the application on an `owner_chain`, lets the owner of a new `spender_chain` spend
+/// some of the tokens, and checks that the allowance can be queried.
+#[tokio::test]
+async fn test_approve() {
+    let initial_amount = Amount::from_tokens(10);
+    let allowance = Amount::from_tokens(3);
+
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut owner_chain = validator.new_chain().await;
+    let owner_account = AccountOwner::from(owner_chain.public_key());
+
+    let initial_state = InitialStateBuilder::default().with_account(owner_account, initial_amount);
+    let params = Parameters::new("APR");
+    let application_id = owner_chain
+        .create_application(module_id, params, initial_state.build(), vec![])
+        .await;
+
+    let spender_chain = validator.new_chain().await;
+    let spender_account = AccountOwner::from(spender_chain.public_key());
+
+    owner_chain
+        .add_block(|block| {
+            block.with_operation(
+                application_id,
+                Operation::Approve {
+                    spender: spender_account,
+                    amount: allowance,
+                },
+            );
+        })
+        .await;
+
+    let query = format!(
+        "query {{ allowance(owner: {}, spender: {}) }}",
+        owner_account.to_value(),
+        spender_account.to_value()
+    );
+    let QueryOutcome { response, .. } = owner_chain.graphql_query(application_id, query).await;
+    let value = response["allowance"]
+        .as_str()
+        .expect("Missing allowance")
+        .parse::<Amount>()
+        .expect("Allowance cannot be parsed as a number");
+    assert_eq!(value, allowance);
+    assert_eq!(
+        fungible::query_account(application_id, &owner_chain, owner_account).await,
+        Some(initial_amount),
+    );
+}
+
+/// Test reading the token metadata.
+///
+/// Creates the application with metadata capping the supply, mints up to the cap, and checks that
+/// the metadata can be queried.
+#[tokio::test]
+async fn test_metadata() {
+    let initial_amount = Amount::from_tokens(8);
+    let max_supply = Amount::from_tokens(10);
+
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut chain = validator.new_chain().await;
+    let account = AccountOwner::from(chain.public_key());
+
+    let initial_state = InitialStateBuilder::default().with_account(account, initial_amount);
+    let params = Parameters {
+        name: "Metadata token".to_string(),
+        decimals: 6,
+        max_supply: Some(max_supply),
+        mintable: true,
+        ..Parameters::new("MTD")
+    };
+    let application_id = chain
+        .create_application(module_id, params, initial_state.build(), vec![])
+        .await;
+
+    chain
+        .add_block(|block| {
+            block
+                .with_operation(application_id, Operation::SetMinter { minter: account })
+                .with_operation(
+                    application_id,
+                    Operation::Mint {
+                        target_account: Account {
+                            chain_id: chain.id(),
+                            owner: account,
+                        },
+                        amount: max_supply.saturating_sub(initial_amount),
+                    },
+                );
+        })
+        .await;
+
+    assert_eq!(
+        fungible::query_account(application_id, &chain, account).await,
+        Some(max_supply),
+    );
+
+    let QueryOutcome { response, .. } = chain
+        .graphql_query(
+            application_id,
+            "query { metadata { tickerSymbol name decimals maxSupply mintable } }",
+        )
+        .await;
+    let metadata = &response["metadata"];
+    assert_eq!(metadata["tickerSymbol"], "MTD");
+    assert_eq!(metadata["name"], "Metadata token");
+    assert_eq!(metadata["decimals"], 6);
+    assert_eq!(metadata["maxSupply"], "10.");
+    assert_eq!(metadata["mintable"], true);
+}
+
+/// Test creating a capped, mintable token without initial accounts.
+///
+/// Creates the application on a `chain` with no initial accounts, checks that the creator gets no
+/// faucet tokens, and mints up to the cap.
+#[tokio::test]
+async fn test_mint_only_token() {
+    let max_supply = Amount::from_tokens(10);
+
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut chain = validator.new_chain().await;
+    let account = AccountOwner::from(chain.public_key());
+
+    let initial_state = InitialStateBuilder::default();
+    let params = Parameters {
+        max_supply: Some(max_supply),
+        mintable: true,
+        ..Parameters::new("MNO")
+    };
+    let application_id = chain
+        .create_application(module_id, params, initial_state.build(), vec![])
+        .await;
+
+    assert_eq!(
+        fungible::query_account(application_id, &chain, account).await,
+        None,
+    );
+    assert_eq!(
+        fungible::query_total_supply(application_id, &chain).await,
+        Amount::ZERO,
+    );
+
+    chain
+        .add_block(|block| {
+            block
+                .with_operation(application_id, Operation::SetMinter { minter: account })
+                .with_operation(
+                    application_id,
+                    Operation::Mint {
+                        target_account: Account {
+                            chain_id: chain.id(),
+                            owner: account,
+                        },
+                        amount: max_supply,
+                    },
+                );
+        })
+        .await;
+
+    assert_eq!(
+        fungible::query_account(application_id, &chain, account).await,
+        Some(max_supply),
+    );
+}
+
+/// Test listing the token holders.
+///
+/// Creates the application with three accounts on a single chain, and checks the holder count,
+/// the top holders and that paging through the holders returns each of them once.
+#[tokio::test]
+async fn test_holders() {
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut token_chain = validator.new_chain().await;
+
+    let mut initial_state = InitialStateBuilder::default();
+    let mut owners = Vec::new();
+    for tokens in [5, 20, 10] {
+        let owner = AccountOwner::from(validator.new_chain().await.public_key());
+        initial_state = initial_state.with_account(owner, Amount::from_tokens(tokens));
+        owners.push(owner);
+    }
+    let params = Parameters::new("HLD");
+    let application_id = token_chain
+        .create_application(module_id, params, initial_state.build(), vec![])
+        .await;
+
+    let QueryOutcome { response, .. } = token_chain
+        .graphql_query(
+            application_id,
+            "query { holderCount topHolders(limit: 2) { owner balance } }",
+        )
+        .await;
+    assert_eq!(response["holderCount"], 3);
+    let top_holders = response["topHolders"]
+        .as_array()
+        .expect("Missing top holders");
+    assert_eq!(top_holders.len(), 2);
+    assert_eq!(top_holders[0]["owner"], owners[1].to_string());
+    assert_eq!(top_holders[0]["balance"], "20.");
+    assert_eq!(top_holders[1]["owner"], owners[2].to_string());
+
+    let mut listed = Vec::new();
+    let mut after = "null".to_string();
+    loop {
+        let query = format!(
+            "query {{ holders(after: {after}, limit: 2) {{ holders {{ owner }} nextCursor }} }}"
+        );
+        let QueryOutcome { response, .. } = token_chain.graphql_query(application_id, query).await;
+        let page = &response["holders"];
+        for holder in page["holders"].as_array().expect("Missing holders") {
+            listed.push(holder["owner"].as_str().expect("Missing owner").to_string());
+        }
+        match page["nextCursor"].as_str() {
+            Some(cursor) => after = format!("\"{cursor}\""),
+            None => break,
+        }
+    }
+    assert_eq!(listed.len(), 3);
+    for owner in &owners {
+        assert!(listed.contains(&owner.to_string()));
+    }
+}
+
+/// Test transferring tokens with a memo.
+///
+/// Creates the application on a `sender_chain` and sends tokens with a memo to a new
+/// `receiver_chain`, which accepts the first transfer and rejects the second. Checks that the
+/// memo reaches the receiver's history and comes back on the bounced record.
+#[tokio::test]
+async fn test_transfer_with_memo() {
+    let initial_amount = Amount::from_tokens(10);
+    let transfer_amount = Amount::from_tokens(3);
+    let memo = b"order-42".to_vec();
+
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut sender_chain = validator.new_chain().await;
+    let sender_account = AccountOwner::from(sender_chain.public_key());
+
+    let initial_state = InitialStateBuilder::default().with_account(sender_account, initial_amount);
+    let params = Parameters::new("MEM");
+    let application_id = sender_chain
+        .create_application(module_id, params, initial_state.build(), vec![])
+        .await;
+
+    let receiver_chain = validator.new_chain().await;
+    let receiver_account = AccountOwner::from(receiver_chain.public_key());
+
+    sender_chain
+        .add_block(|block| {
+            block.with_operation(
+                application_id,
+                Operation::TransferWithMemo {
+                    owner: sender_account,
+                    amount: transfer_amount,
+                    target_account: Account {
+                        chain_id: receiver_chain.id(),
+                        owner: receiver_account,
+                    },
+                    memo: Some(memo.clone()),
+                },
+            );
+        })
+        .await;
+    receiver_chain.handle_received_messages().await;
+
+    let query = format!(
+        "query {{ history(owner: {}) {{ direction counterparty memo }} }}",
+        receiver_account.to_value()
+    );
+    let QueryOutcome { response, .. } = receiver_chain.graphql_query(application_id, query).await;
+    let record = &response["history"][0];
+    assert_eq!(record["direction"], "INCOMING");
+    assert_eq!(record["counterparty"], sender_account.to_string());
+    let record_memo = record["memo"]
+        .as_array()
+        .expect("Missing memo")
+        .iter()
+        .map(|byte| byte.as_u64().expect("Invalid memo byte") as u8)
+        .collect::<Vec<_>>();
+    assert_eq!(record_memo, memo);
+
+    let certificate = sender_chain
+        .add_block(|block| {
+            block.with_operation(
+                application_id,
+                Operation::TransferWithMemo {
+                    owner: sender_account,
+                    amount: transfer_amount,
+                    target_account: Account {
+                        chain_id: receiver_chain.id(),
+                        owner: receiver_account,
+                    },
+                    memo: Some(memo.clone()),
+                },
+            );
+        })
+        .await;
+
+    receiver_chain
+        .add_block(move |block| {
+            block.with_messages_from_by_medium(
+                &certificate,
+                &Medium::Direct,
+                MessageAction::Reject,
+            );
+        })
+        .await;
+    sender_chain.handle_received_messages().await;
+
+    assert_eq!(
+        fungible::query_account(application_id, &sender_chain, sender_account).await,
+        Some(initial_amount.saturating_sub(transfer_amount)),
+    );
+    let query = format!(
+        "query {{ history(owner: {}, limit: 1) {{ direction bounced memo }} }}",
+        sender_account.to_value()
+    );
+    let QueryOutcome { response, .. } = sender_chain.graphql_query(application_id, query).await;
+    let record = &response["history"][0];
+    assert_eq!(record["direction"], "INCOMING");
+    assert_eq!(record["bounced"], true);
+    let record_memo = record["memo"]
+        .as_array()
+        .expect("Missing memo")
+        .iter()
+        .map(|byte| byte.as_u64().expect("Invalid memo byte") as u8)
+        .collect::<Vec<_>>();
+    assert_eq!(record_memo, memo);
+}
+
+/// Test that a memo longer than `MAX_MEMO_LEN` bytes is rejected.
+#[tokio::test]
+#[should_panic]
+async fn test_memo_too_long() {
+    let (validator, module_id) =
+        TestValidator::with_current_module::<FungibleTokenAbi, Parameters, InitialState>().await;
+    let mut chain = validator.new_chain().await;
+    let account = AccountOwner::from(chain.public_key());
+    let chain_id = chain.id();
+
+    let initial_state =
+        InitialStateBuilder::default().with_account(account, Amount::from_tokens(10));
+    let application_id = chain
+        .create_application(
+            module_id,
+            Parameters::new("MEM"),
+            initial_state.build(),
+            vec![],
+        )
+        .await;
+
+    chain
+        .add_block(|block| {
+            block.with_operation(
+                application_id,
+                Operation::TransferWithMemo {
+                    owner: account,
+                    amount: Amount::ONE,
+                    target_account: Account {
+                        chain_id,
+                        owner: account,
+                    },
+                    memo: Some(vec![0; MAX_MEMO_LEN + 1]),
+                },
+            );
+        })
+        .await;
+}
@@ -5,13 +5,16 @@ use std::str::FromStr;
 
 use fungible::{Account as FungibleAccount, FungibleTokenAbi};
 use linera_sdk::{
-    linera_base_types::{Account, AccountOwner, Amount, ApplicationId, ChainId, CryptoHash, WithContractAbi},
+    linera_base_types::{Account, AccountOwner, Amount, ApplicationId, ChainId, CryptoHash, StreamName, WithContractAbi},
     views::{RootView, View},
     Contract, ContractRuntime,
 };
 
 use log::warn;
-use lst::{LstAbi, Message, Operation, Parameters};
+use lst::{
+    fee_for, lst_to_native, mul_div, native_to_lst, request_memo, InstantiationArgument, LstAbi, LstAction, LstConfig, LstError, LstEvent, LstResponse, Message, Operation, Parameters, PauseFlags,
+    RateSource, LST_STREAM_NAME,
+};
 use state::LstState;
 
 pub struct LstContract {
@@ -26,88 +29,339 @@ impl WithContractAbi for LstContract {
 
 impl Contract for LstContract {
     type Message = Message;
-    type InstantiationArgument = ();
+    type InstantiationArgument = InstantiationArgument;
     type Parameters = Parameters;
-    type EventValue = ();
+    type EventValue = LstEvent;
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
         let state = LstState::load(runtime.root_view_storage_context()).await.expect("Failed to load state");
         LstContract { state, runtime }
     }
 
-    async fn instantiate(&mut self, _: ()) {
+    async fn instantiate(&mut self, argument: InstantiationArgument) {
         // Validate that the application parameters were configured correctly.
         let protocol_lst = self.runtime.application_parameters().get_protocol_lst();
 
+        self.state.admin.set(Some(argument.admin));
+        self.state.withdrawal_cooldown.set(argument.withdrawal_cooldown);
+
         // self.state.protocol_lst_id.set(Some(protocol_lst.forget_abi()));
 
-        self.state.approved_lst_set.insert(&protocol_lst.forget_abi()).expect("Failed to insert protocol lst id");
+        let fungible::FungibleResponse::TickerSymbol(ticker) = self.runtime.call_application(false, protocol_lst, &fungible::Operation::TickerSymbol) else {
+            panic!("Unexpected response to a ticker symbol query");
+        };
+        let config = LstConfig {
+            rate_source: RateSource::Pool,
+            ..LstConfig::new(ticker)
+        };
+        self.state.lst_configs.insert(&protocol_lst.forget_abi(), config).expect("Failed to insert protocol lst config");
     }
 
     async fn execute_operation(&mut self, operation: Operation) -> Self::Response {
         match operation {
-            Operation::NewLst { token_id } => {
-                //TODO add check
-                self.state.approved_lst_set.insert(&token_id).expect("Failed to insert token id");
+            Operation::NewLst { token_id, config } => {
+                self.check_admin()?;
+                if self.state.lst_config(&token_id).await.is_some() {
+                    return Err(LstError::LstAlreadyListed(token_id));
+                }
+                self.check_config(&token_id, &config)?;
+                self.state.lst_configs.insert(&token_id, config.clone()).expect("Failed to insert lst config");
+                self.emit(LstEvent::LstListed { token_id, config });
+                Ok(LstResponse::Ok)
+            }
+            Operation::UpdateLst { token_id, config } => {
+                self.check_admin()?;
+                if self.state.lst_config(&token_id).await.is_none() {
+                    return Err(LstError::LstNotApproved(token_id));
+                }
+                self.check_config(&token_id, &config)?;
+                self.state.lst_configs.insert(&token_id, config.clone()).expect("Failed to insert lst config");
+                self.emit(LstEvent::LstUpdated { token_id, config });
+                Ok(LstResponse::Ok)
+            }
+            Operation::DelistLst { token_id } => {
+                self.check_admin()?;
+                if self.is_protocol_lst(&token_id) {
+                    return Err(LstError::ProtocolLstDelist);
+                }
+                if self.state.lst_config(&token_id).await.is_none() {
+                    return Err(LstError::LstNotApproved(token_id));
+                }
+                // delisting would strand the funds, and users would be repriced if it came back
+                if self.state.has_funds(&token_id).await {
+                    return Err(LstError::LstHasFunds(token_id));
+                }
+                self.state.lst_configs.remove(&token_id).expect("Failed to remove lst config");
+                self.emit(LstEvent::LstDelisted { token_id });
+                Ok(LstResponse::Ok)
+            }
+            Operation::UpdateExchangeRate { token_id, rate } => {
+                self.check_admin()?;
+                if rate == Amount::ZERO {
+                    return Err(LstError::InvalidExchangeRate);
+                }
+                // the protocol lst is priced by the pool, not by a fixed rate
+                if self.is_protocol_lst(&token_id) {
+                    return Err(LstError::ProtocolLstRate);
+                }
+                if self.state.lst_config(&token_id).await.is_none() {
+                    return Err(LstError::LstNotApproved(token_id));
+                }
+                self.state.exchange_rates.insert(&token_id, rate).expect("Failed to insert exchange rate");
+                self.emit(LstEvent::RateUpdated { token_id, rate });
+                Ok(LstResponse::Ok)
+            }
+            Operation::SetPaused { token_id, flags, chains } => {
+                self.check_admin()?;
+                self.state.set_paused(token_id, flags);
+                for chain_id in chains {
+                    self.runtime.prepare_message(Message::SetPaused { token_id, flags }).send_to(chain_id);
+                }
+                self.emit(LstEvent::PauseUpdated { token_id, flags });
+                Ok(LstResponse::Ok)
+            }
+            Operation::ConfigureOracle {
+                oracle,
+                max_reward_bps,
+                epoch_duration,
+            } => {
+                self.check_admin()?;
+                self.state.oracle.set(Some(oracle));
+                self.state.max_reward_bps.set(max_reward_bps);
+                self.state.epoch_duration.set(epoch_duration);
+                self.emit(LstEvent::OracleConfigured {
+                    oracle,
+                    max_reward_bps,
+                    epoch_duration,
+                });
+                Ok(LstResponse::Ok)
+            }
+            Operation::ReportRewards { amount, epoch } => {
+                self.check_oracle()?;
+                let now = self.runtime.system_time();
+                self.state.report_rewards(amount, epoch, now)?;
+                let protocol_lst_price = self.state.native_for_shares(Amount::ONE);
+                self.emit(LstEvent::RewardsReported { epoch, amount, protocol_lst_price });
+                Ok(LstResponse::Ok)
+            }
+            Operation::ReportLoss { token_id, loss_bps, pause } => {
+                self.check_oracle()?;
+                if loss_bps == 0 || loss_bps > 10_000 {
+                    return Err(LstError::InvalidLoss);
+                }
+                let native_loss = match token_id {
+                    None => {
+                        // wiping out the pool while shares are outstanding would reset their price
+                        if loss_bps == 10_000 {
+                            return Err(LstError::InvalidLoss);
+                        }
+                        let pooled = *self.state.total_pooled_native.get();
+                        self.state.socialize_loss(fee_for(pooled, loss_bps))
+                    }
+                    Some(token_id) => {
+                        if self.is_protocol_lst(&token_id) {
+                            return Err(LstError::ProtocolLstRate);
+                        }
+                        if self.state.lst_config(&token_id).await.is_none() {
+                            return Err(LstError::LstNotApproved(token_id));
+                        }
+                        let old_rate = self.state.exchange_rate(&token_id).await;
+                        let new_rate = old_rate.saturating_sub(fee_for(old_rate, loss_bps));
+                        if new_rate == Amount::ZERO {
+                            return Err(LstError::InvalidLoss);
+                        }
+                        self.state.exchange_rates.insert(&token_id, new_rate).expect("Failed to insert exchange rate");
+                        self.emit(LstEvent::RateUpdated { token_id, rate: new_rate });
+
+                        // the lst exchanged for protocol lst backs the pool, so its holders share the loss
+                        let backing = self.state.pool_backing(&token_id).await;
+                        let native_loss = lst_to_native(backing, old_rate).saturating_sub(lst_to_native(backing, new_rate));
+                        self.state.socialize_loss(native_loss)
+                    }
+                };
+                if pause {
+                    let flags = PauseFlags {
+                        stake: true,
+                        swap: true,
+                        ..self.state.pause_flags(token_id).await
+                    };
+                    self.state.set_paused(token_id, flags);
+                    self.emit(LstEvent::PauseUpdated { token_id, flags });
+                }
+                let protocol_lst_price = self.state.native_for_shares(Amount::ONE);
+                self.emit(LstEvent::LossReported {
+                    token_id,
+                    loss_bps,
+                    native_loss,
+                    protocol_lst_price,
+                });
+                Ok(LstResponse::Ok)
+            }
+            Operation::ConfigureInstantUnstake { config } => {
+                self.check_admin()?;
+                if config.min_fee_bps > config.max_fee_bps || config.max_fee_bps > 10_000 {
+                    return Err(LstError::InvalidInstantUnstakeConfig);
+                }
+                self.state.instant_unstake.set(config);
+                let buffer = (*self.state.liquidity_buffer.get()).min(config.buffer_target);
+                self.state.liquidity_buffer.set(buffer);
+                self.emit(LstEvent::InstantUnstakeConfigured { config, liquidity_buffer: buffer });
+                Ok(LstResponse::Ok)
             }
-            Operation::StakeNative { user, amount, lst_type_out } => {
-                // transfer the native token to the contract
-                let chain_id = self.runtime.application_creator_chain_id();
+            Operation::ProvideLiquidity { owner, token_id, amount } => {
+                self.check_admin()?;
+                self.check_owner(owner)?;
+                if amount == Amount::ZERO {
+                    return Err(LstError::ZeroAmount);
+                }
+                let transfer = fungible::Operation::Transfer {
+                    owner,
+                    amount,
+                    target_account: FungibleAccount {
+                        chain_id: self.runtime.chain_id(),
+                        owner: self.runtime.application_id().into(),
+                    },
+                };
+                self.runtime.call_application(/* authenticated by owner */ true, token_id.with_abi::<FungibleTokenAbi>(), &transfer);
+                self.state.add_reserves(token_id, amount).await;
+                self.emit(LstEvent::LiquidityProvided { token_id, amount });
+                Ok(LstResponse::Ok)
+            }
+            Operation::ProvideNativeLiquidity { owner, amount } => {
+                self.check_admin()?;
+                self.check_owner(owner)?;
+                if amount == Amount::ZERO {
+                    return Err(LstError::ZeroAmount);
+                }
+                let vault_account = Account {
+                    chain_id: self.runtime.chain_id(),
+                    owner: self.runtime.application_id().into(),
+                };
+                self.runtime.transfer(owner, vault_account, amount);
+                self.state.native_liquidity.get_mut().saturating_add_assign(amount);
+                self.emit(LstEvent::NativeLiquidityProvided { amount });
+                Ok(LstResponse::Ok)
+            }
+            Operation::WithdrawFees { token_id, amount, target_account } => {
+                self.check_admin()?;
+                if amount == Amount::ZERO {
+                    return Err(LstError::ZeroAmount);
+                }
+                self.state.take_fees(token_id, amount).await?;
+                let transfer = fungible::Operation::Transfer {
+                    owner: self.runtime.application_id().into(),
+                    amount,
+                    target_account: FungibleAccount {
+                        chain_id: target_account.chain_id,
+                        owner: target_account.owner,
+                    },
+                };
+                self.runtime.call_application(true, token_id.with_abi::<FungibleTokenAbi>(), &transfer);
+                self.emit(LstEvent::FeesWithdrawn { token_id, amount, target_account });
+                Ok(LstResponse::Ok)
+            }
+            Operation::StakeNative {
+                user,
+                amount,
+                lst_type_out,
+                min_amount_out,
+            } => {
+                self.check_owner(user)?;
+                if amount == Amount::ZERO {
+                    return Err(LstError::ZeroAmount);
+                }
+                self.state.check_not_paused(LstAction::Stake, &[lst_type_out.forget_abi()]).await?;
+                // transfer the native token to the contract, through its account on this chain so a
+                // bounced transfer comes back under the contract's custody
                 let app_owner: AccountOwner = self.runtime.application_id().into();
+                let custody_account = Account {
+                    chain_id: self.runtime.chain_id(),
+                    owner: app_owner,
+                };
+                let vault_account = Account {
+                    chain_id: self.runtime.application_creator_chain_id(),
+                    owner: app_owner,
+                };
 
                 // to do add option with remote transfer
-                self.runtime.transfer(user, Account { chain_id, owner: app_owner }, amount);
+                self.runtime.transfer(user, custody_account, amount);
+                self.runtime.transfer(app_owner, vault_account, amount);
 
                 // send message to stake chain to finish the stake
+                let request_id = self.state.next_request_id();
                 let message = Message::StakeNative {
                     user,
                     amount,
                     lst_type_out: lst_type_out.forget_abi(),
                     user_chain_id: self.runtime.chain_id(),
+                    min_amount_out,
+                    request_id,
                 };
-                let dest_chain_id = self.get_app_chain_id();
-
-                self.runtime.prepare_message(message).with_authentication().send_to(dest_chain_id);
+                self.send_request(message);
+                self.emit(LstEvent::RequestSent {
+                    user,
+                    request_id,
+                    lst_type_in: None,
+                    amount_in: amount,
+                });
+                Ok(LstResponse::RequestSent { request_id })
             }
             Operation::StakeLst { user, amount, lst_type_in } => {
+                self.check_user(user).await?;
+                if amount == Amount::ZERO {
+                    return Err(LstError::ZeroAmount);
+                }
+                let protocol_lst = self.runtime.application_parameters().get_protocol_lst().forget_abi();
+                self.state.check_not_paused(LstAction::Stake, &[lst_type_in, protocol_lst]).await?;
+                let request_id = self.state.next_request_id();
                 // to do add option with remote transfer
-                self.receive_from_user(user, amount, lst_type_in.with_abi::<FungibleTokenAbi>());
+                self.receive_from_user(user, amount, lst_type_in.with_abi::<FungibleTokenAbi>(), Some(request_id));
 
                 // send message to stake chain to finish the stake
                 let message = Message::StakeLst {
                     user,
                     amount_in: amount,
                     user_chain_id: self.runtime.chain_id(),
+                    lst_type_in,
+                    request_id,
                 };
-                let dest_chain_id = self.get_app_chain_id();
-
-                self.runtime.prepare_message(message).with_authentication().send_to(dest_chain_id);
+                self.send_request(message);
+                self.emit(LstEvent::RequestSent {
+                    user,
+                    request_id,
+                    lst_type_in: Some(lst_type_in),
+                    amount_in: amount,
+                });
+                Ok(LstResponse::RequestSent { request_id })
             }
-            Operation::Unstake { owner, amount } => {
-                // Check if the user has a stake
-                let current_amount = match self.state.stake_balances.get(&owner).await {
-                    Ok(Some(current)) => current,
-                    Ok(None) => panic!("No stake found for user"),
-                    Err(e) => panic!("Failed to get stake balance: {}", e),
-                };
-
-                // Ensure the user has enough stake to unstake
-                if current_amount < amount {
-                    panic!("Insufficient stake balance");
-                }
+            Operation::Unstake { owner, amount } => self.request_unstake(owner, amount, false).await,
+            Operation::InstantUnstake { owner, amount } => self.request_unstake(owner, amount, true).await,
+            Operation::ClaimWithdrawal { owner, request_id } => {
+                // the request is paid out to its owner, so anyone may trigger the claim
+                let message = Message::ClaimWithdrawal { owner, request_id };
+                let dest_chain_id = self.get_app_chain_id();
 
-                // Update the stake by subtracting the amount
-                let new_amount = current_amount.try_sub(amount).expect("Failed to subtract stake balance");
-                self.state.stake_balances.insert(&owner, new_amount).expect("Failed to insert stake balance");
+                self.runtime.prepare_message(message).send_to(dest_chain_id);
+                Ok(LstResponse::Ok)
             }
             Operation::Swap {
                 user,
                 amount_in,
                 lst_type_in,
                 lst_type_out,
+                min_amount_out,
             } => {
+                self.check_user(user).await?;
+                if amount_in == Amount::ZERO {
+                    return Err(LstError::ZeroAmount);
+                }
+                if lst_type_in == lst_type_out {
+                    return Err(LstError::SameLstType);
+                }
+                self.state.check_not_paused(LstAction::Swap, &[lst_type_in, lst_type_out]).await?;
+                let request_id = self.state.next_request_id();
                 // to do add option with remote transfer
-                self.receive_from_user(user, amount_in, lst_type_in.with_abi::<FungibleTokenAbi>());
+                self.receive_from_user(user, amount_in, lst_type_in.with_abi::<FungibleTokenAbi>(), Some(request_id));
 
                 // send message to stake chain to finish the stake
                 let message = Message::Swap {
@@ -116,47 +370,217 @@ impl Contract for LstContract {
                     lst_type_in,
                     lst_type_out,
                     user_chain_id: self.runtime.chain_id(),
+                    min_amount_out,
+                    request_id,
                 };
-                let dest_chain_id = self.get_app_chain_id();
-
-                self.runtime.prepare_message(message).with_authentication().send_to(dest_chain_id);
+                self.send_request(message);
+                self.emit(LstEvent::RequestSent {
+                    user,
+                    request_id,
+                    lst_type_in: Some(lst_type_in),
+                    amount_in,
+                });
+                Ok(LstResponse::RequestSent { request_id })
+            }
+            Operation::SetOperator { operator, approved } => {
+                let owner = self.runtime.authenticated_signer().ok_or(LstError::NotSigned)?;
+                self.state.set_operator(owner, operator, approved).await;
+                self.emit(LstEvent::OperatorUpdated { owner, operator, approved });
+                Ok(LstResponse::Ok)
+            }
+            Operation::Quote { amount_in, lst_type_in, lst_type_out } => {
+                let config_out = self.check_approved(&lst_type_out).await?;
+                let (native_value, fee_bps) = match lst_type_in {
+                    Some(lst_type_in) => {
+                        self.check_approved(&lst_type_in).await?;
+                        (self.native_for_amount(lst_type_in, amount_in).await, config_out.swap_fee_bps)
+                    }
+                    None if !config_out.native_staking => return Err(LstError::NativeStakingNotAllowed(lst_type_out)),
+                    None => (amount_in, config_out.stake_fee_bps),
+                };
+                let gross_out = self.amount_for_native(lst_type_out, native_value).await;
+                if !self.can_pay_out(&lst_type_out, gross_out).await {
+                    return Err(LstError::InsufficientReserves(lst_type_out));
+                }
+                let amount_out = gross_out.saturating_sub(fee_for(gross_out, fee_bps));
+                Ok(LstResponse::Quote { amount_out })
             }
             Operation::Test => {
                 println!("Test operation");
+                Ok(LstResponse::Ok)
             }
         }
     }
 
     async fn execute_message(&mut self, message: Message) {
+        let is_bouncing = self.runtime.message_is_bouncing().expect("Delivery status is available when executing a message");
+        if is_bouncing {
+            // the stake chain rejected the request, so its input is back in custody on this chain
+            self.refund_bounced_message(message);
+            return;
+        }
+
         match message {
             Message::StakeNative {
                 user,
                 amount,
                 lst_type_out,
                 user_chain_id,
+                min_amount_out,
+                request_id,
             } => {
-                // check if the lst_type_out is approved
-                let is_protocol_lst = lst_type_out == self.runtime.application_parameters().get_protocol_lst().forget_abi();
-                let is_approved = self.state.approved_lst_set.contains(&lst_type_out).await.unwrap();
-                // type out must be approved or the protocol lst
-                if !is_approved && !is_protocol_lst {
-                    panic!("Lst type out is not approved");
+                // type out must be approved and accept native stake
+                let config = self.check_approved(&lst_type_out).await.unwrap_or_else(|error| panic!("{error}"));
+                if !config.native_staking {
+                    panic!("{}", LstError::NativeStakingNotAllowed(lst_type_out));
                 }
+                // rejecting a paused request bounces it back to the user chain for a refund
+                self.state.check_not_paused(LstAction::Stake, &[lst_type_out]).await.unwrap_or_else(|error| panic!("{error}"));
 
-                //TODO get cureent lst price, for now we assume 1:1
-                let _current_price = Amount::ONE;
-                // let amount_out = amount.try_mul(current_price.into()).expect("Failed to multiply amount");
-                // let lst_type_out_id = lst_type_out.with_abi::<FungibleTokenAbi>();
-                let amount_out = amount;
-                // TODO: ADD CHECK FOR TRANSFER AUTHORIZATION!!!
-                self.send_to_user(amount_out, user, lst_type_out.with_abi::<FungibleTokenAbi>(), user_chain_id);
+                let gross_out = self.amount_for_native(lst_type_out, amount).await;
+                let fee = fee_for(gross_out, config.stake_fee_bps);
+                let amount_out = gross_out.saturating_sub(fee);
+                if amount_out < min_amount_out || !self.can_pay_out(&lst_type_out, gross_out).await {
+                    // the rate moved since the user submitted the stake, or the vault can't pay out,
+                    // so return the native tokens
+                    self.refund(user, user_chain_id, request_id, None, amount);
+                    return;
+                }
+                if self.is_protocol_lst(&lst_type_out) {
+                    // the fee shares are issued too, and stay in the vault for the treasury
+                    self.state.add_to_pool(amount, gross_out);
+                    self.state.native_liquidity.get_mut().saturating_add_assign(amount);
+                    self.state.refill_buffer(amount);
+                }
+                self.pay_out(lst_type_out, amount_out, fee, user, user_chain_id).await;
+                self.emit(LstEvent::Staked {
+                    user,
+                    user_chain_id,
+                    request_id,
+                    lst_type_in: None,
+                    amount_in: amount,
+                    lst_type_out,
+                    amount_out,
+                    fee,
+                });
             }
             Message::StakeLocalAccount { owner, amount } => {
                 self.stake_from_local_account(owner, amount).await;
             }
-            Message::StakeLst { user, amount_in, user_chain_id } => {
+            Message::StakeLst {
+                user,
+                amount_in,
+                user_chain_id,
+                lst_type_in,
+                request_id,
+            } => {
+                let config_in = self.check_approved(&lst_type_in).await.unwrap_or_else(|error| panic!("{error}"));
+
+                let protocol_lst = self.runtime.application_parameters().get_protocol_lst();
+                self.state
+                    .check_not_paused(LstAction::Stake, &[lst_type_in, protocol_lst.forget_abi()])
+                    .await
+                    .unwrap_or_else(|error| panic!("{error}"));
+                let native_value = self.native_for_amount(lst_type_in, amount_in).await;
+                let gross_out = self.amount_for_native(protocol_lst.forget_abi(), native_value).await;
+                let is_protocol_lst_in = self.is_protocol_lst(&lst_type_in);
+                let within_limits = is_protocol_lst_in || self.state.check_deposit(&config_in, &lst_type_in, &user, amount_in).await.is_ok();
+                if !within_limits {
+                    // the vault can't take more of this lst, so return it
+                    self.refund(user, user_chain_id, request_id, Some(lst_type_in), amount_in);
+                    return;
+                }
+                if is_protocol_lst_in {
+                    self.state.remove_from_pool(native_value, amount_in);
+                } else {
+                    self.state.record_deposit(lst_type_in, user, amount_in).await;
+                    self.state.add_pool_backing(lst_type_in, amount_in).await;
+                }
+                self.take_in(lst_type_in, amount_in).await;
+                let fee = fee_for(gross_out, config_in.stake_fee_bps);
+                let amount_out = gross_out.saturating_sub(fee);
+                self.state.add_to_pool(native_value, gross_out);
+                self.pay_out(protocol_lst.forget_abi(), amount_out, fee, user, user_chain_id).await;
+                self.emit(LstEvent::Staked {
+                    user,
+                    user_chain_id,
+                    request_id,
+                    lst_type_in: Some(lst_type_in),
+                    amount_in,
+                    lst_type_out: protocol_lst.forget_abi(),
+                    amount_out,
+                    fee,
+                });
+            }
+            Message::Unstake {
+                user,
+                amount,
+                user_chain_id,
+                request_id,
+                instant,
+            } => {
                 let protocol_lst = self.runtime.application_parameters().get_protocol_lst();
-                self.send_to_user(amount_in, user, protocol_lst, user_chain_id);
+                self.state
+                    .check_not_paused(LstAction::Unstake, &[protocol_lst.forget_abi()])
+                    .await
+                    .unwrap_or_else(|error| panic!("{error}"));
+                let native_out = self.native_for_amount(protocol_lst.forget_abi(), amount).await;
+
+                let instant_fee = if instant { self.state.instant_unstake_fee(native_out) } else { None };
+                if let Some(fee) = instant_fee {
+                    // the fee stays in the pool, raising the price for the remaining holders
+                    let paid = native_out.saturating_sub(fee);
+                    self.state.liquidity_buffer.get_mut().try_sub_assign(paid).expect("Buffer covers the payout");
+                    self.state.reserve_native(paid).expect("Native liquidity covers the buffer");
+                    self.state.remove_from_pool(paid, amount);
+                    self.burn_protocol_lst(amount);
+                    self.send_native_to_user(paid, user, user_chain_id);
+                    self.emit(LstEvent::InstantUnstaked {
+                        user,
+                        user_chain_id,
+                        request_id,
+                        shares: amount,
+                        native_amount: paid,
+                        fee,
+                    });
+                    return;
+                }
+
+                // part of the pool may be backed by lst deposits rather than native tokens, so the
+                // request bounces back to the user chain if the vault can't pay it out
+                self.state.reserve_native(native_out).unwrap_or_else(|error| panic!("{error}"));
+                self.state.remove_from_pool(native_out, amount);
+                self.burn_protocol_lst(amount);
+
+                // the native tokens stay in the vault until the cooldown is over
+                let now = self.runtime.system_time();
+                let withdrawal_id = self.state.add_withdrawal_request(user, user_chain_id, native_out, now).await;
+                self.emit(LstEvent::Unstaked {
+                    user,
+                    user_chain_id,
+                    request_id,
+                    shares: amount,
+                    native_amount: native_out,
+                    withdrawal_id,
+                });
+            }
+            Message::ClaimWithdrawal { owner, request_id } => {
+                let now = self.runtime.system_time();
+                let request = self.state.take_matured_withdrawal(owner, request_id, now).await.unwrap_or_else(|error| panic!("{error}"));
+                self.send_native_to_user(request.amount, owner, request.chain_id);
+                self.emit(LstEvent::WithdrawalClaimed {
+                    owner,
+                    chain_id: request.chain_id,
+                    withdrawal_id: request.id,
+                    amount: request.amount,
+                });
+            }
+            Message::SetPaused { token_id, flags } => {
+                // the stake chain checks the admin before forwarding the flags
+                let origin = self.runtime.message_origin_chain_id().expect("Origin is available when executing a message");
+                assert_eq!(origin, self.runtime.application_creator_chain_id(), "Pause flags can only be set by the stake chain");
+                self.state.set_paused(token_id, flags);
+                self.emit(LstEvent::PauseUpdated { token_id, flags });
             }
             Message::Swap {
                 user,
@@ -164,29 +588,59 @@ impl Contract for LstContract {
                 user_chain_id,
                 lst_type_in,
                 lst_type_out,
+                min_amount_out,
+                request_id,
             } => {
-                // check if the lst_type_out is approved
-                let is_protocol_lst = lst_type_out == self.runtime.application_parameters().get_protocol_lst().forget_abi();
-                let is_approved = self.state.approved_lst_set.contains(&lst_type_out).await.unwrap();
-                // type out must be approved or the protocol lst
-                if !is_approved && !is_protocol_lst {
-                    panic!("Lst type out is not approved");
+                // both types must be approved
+                let config_out = self.check_approved(&lst_type_out).await.unwrap_or_else(|error| panic!("{error}"));
+                let config_in = self.check_approved(&lst_type_in).await.unwrap_or_else(|error| panic!("{error}"));
+                self.state
+                    .check_not_paused(LstAction::Swap, &[lst_type_in, lst_type_out])
+                    .await
+                    .unwrap_or_else(|error| panic!("{error}"));
+
+                // price both sides in native tokens
+                let native_value = self.native_for_amount(lst_type_in, amount_in).await;
+                let gross_out = self.amount_for_native(lst_type_out, native_value).await;
+                let fee = fee_for(gross_out, config_out.swap_fee_bps);
+                let amount_out = gross_out.saturating_sub(fee);
+                let is_protocol_lst_in = self.is_protocol_lst(&lst_type_in);
+                let within_limits = is_protocol_lst_in || self.state.check_deposit(&config_in, &lst_type_in, &user, amount_in).await.is_ok();
+                if amount_out < min_amount_out || !within_limits || !self.can_pay_out(&lst_type_out, gross_out).await {
+                    // the rates moved since the user submitted the swap, or the vault can't take
+                    // more of the input or pay out the output, so return the input
+                    self.refund(user, user_chain_id, request_id, Some(lst_type_in), amount_in);
+                    return;
                 }
-                // check if the lst_type_in is approved
-                let is_protocol_lst = lst_type_in == self.runtime.application_parameters().get_protocol_lst().forget_abi();
-                let is_approved = self.state.approved_lst_set.contains(&lst_type_in).await.unwrap();
-                // type out must be approved or the protocol lst
-                if !is_approved && !is_protocol_lst {
-                    panic!("Lst type in is not approved");
+                if is_protocol_lst_in {
+                    self.state.remove_from_pool(native_value, amount_in);
+                    self.state.remove_pool_backing(lst_type_out, gross_out).await;
+                } else {
+                    self.state.record_deposit(lst_type_in, user, amount_in).await;
+                    if !self.is_protocol_lst(&lst_type_out) {
+                        // the input takes over the backing of the lst paid out of the pool
+                        let moved = self.state.remove_pool_backing(lst_type_out, gross_out).await;
+                        if moved > Amount::ZERO {
+                            self.state.add_pool_backing(lst_type_in, mul_div(amount_in, moved, gross_out)).await;
+                        }
+                    }
                 }
-
-                //TODO get cureent lst price, for now we assume 1:1
-                let _current_price = Amount::ONE;
-                // let amount_out = amount.try_mul(current_price.into()).expect("Failed to multiply amount");
-                // let lst_type_out_id = lst_type_out.with_abi::<FungibleTokenAbi>();
-                let amount_out = amount_in;
-                // TODO: ADD CHECK FOR TRANSFER AUTHORIZATION!!!
-                self.send_to_user(amount_out, user, lst_type_out.with_abi::<FungibleTokenAbi>(), user_chain_id);
+                self.take_in(lst_type_in, amount_in).await;
+                if self.is_protocol_lst(&lst_type_out) {
+                    self.state.add_to_pool(native_value, gross_out);
+                    self.state.add_pool_backing(lst_type_in, amount_in).await;
+                }
+                self.pay_out(lst_type_out, amount_out, fee, user, user_chain_id).await;
+                self.emit(LstEvent::Swapped {
+                    user,
+                    user_chain_id,
+                    request_id,
+                    lst_type_in,
+                    amount_in,
+                    lst_type_out,
+                    amount_out,
+                    fee,
+                });
             }
         }
     }
@@ -206,6 +660,133 @@ impl LstContract {
     fn get_app_chain_id(&mut self) -> ChainId {
         self.runtime.application_creator_chain_id()
     }
+
+    /// Checks that the current operation is signed by the admin.
+    ///
+    /// The admin is only known on the stake chain, so configuration can't be changed elsewhere.
+    fn check_admin(&mut self) -> Result<(), LstError> {
+        let signer = self.runtime.authenticated_signer();
+        if signer.is_none() || signer != *self.state.admin.get() {
+            return Err(LstError::NotAdmin);
+        }
+        Ok(())
+    }
+
+    /// Checks that the current operation is signed by the oracle.
+    fn check_oracle(&mut self) -> Result<(), LstError> {
+        let signer = self.runtime.authenticated_signer();
+        if signer.is_none() || signer != *self.state.oracle.get() {
+            return Err(LstError::NotOracle);
+        }
+        Ok(())
+    }
+
+    /// Checks that `owner` signed the operation, or is the application calling this one.
+    fn check_owner(&mut self, owner: AccountOwner) -> Result<(), LstError> {
+        self.runtime.check_account_permission(owner).map_err(|_| LstError::Unauthorized(owner))
+    }
+
+    /// Checks that `user` signed the operation, or approved the signer or calling application as
+    /// an operator on this chain.
+    async fn check_user(&mut self, user: AccountOwner) -> Result<(), LstError> {
+        if self.check_owner(user).is_ok() {
+            return Ok(());
+        }
+        let signer = self.runtime.authenticated_signer();
+        let caller = self.runtime.authenticated_caller_id().map(AccountOwner::from);
+        for operator in [signer, caller].into_iter().flatten() {
+            if self.state.is_operator(&user, &operator).await {
+                return Ok(());
+            }
+        }
+        Err(LstError::Unauthorized(user))
+    }
+
+    /// Checks that `token_id` is listed and enabled, returning its configuration.
+    async fn check_approved(&mut self, token_id: &ApplicationId) -> Result<LstConfig, LstError> {
+        match self.state.lst_config(token_id).await {
+            Some(config) if config.enabled => Ok(config),
+            _ => Err(LstError::LstNotApproved(*token_id)),
+        }
+    }
+
+    /// Checks that `config` is consistent for `token_id`.
+    fn check_config(&mut self, token_id: &ApplicationId, config: &LstConfig) -> Result<(), LstError> {
+        let expected_source = if self.is_protocol_lst(token_id) { RateSource::Pool } else { RateSource::Admin };
+        if config.rate_source != expected_source || config.stake_fee_bps > 10_000 || config.swap_fee_bps > 10_000 {
+            return Err(LstError::InvalidLstConfig);
+        }
+        Ok(())
+    }
+
+    /// Sends the protocol LST of `owner` to the stake chain to be redeemed for native tokens.
+    async fn request_unstake(&mut self, owner: AccountOwner, amount: Amount, instant: bool) -> Result<LstResponse, LstError> {
+        self.check_user(owner).await?;
+        if amount == Amount::ZERO {
+            return Err(LstError::ZeroAmount);
+        }
+        // move the protocol lst shares to the vault
+        let protocol_lst = self.runtime.application_parameters().get_protocol_lst();
+        self.state.check_not_paused(LstAction::Unstake, &[protocol_lst.forget_abi()]).await?;
+        let request_id = self.state.next_request_id();
+        self.receive_from_user(owner, amount, protocol_lst, Some(request_id));
+
+        // send message to stake chain to redeem the shares
+        let message = Message::Unstake {
+            user: owner,
+            amount,
+            user_chain_id: self.runtime.chain_id(),
+            request_id,
+            instant,
+        };
+        self.send_request(message);
+        self.emit(LstEvent::RequestSent {
+            user: owner,
+            request_id,
+            lst_type_in: Some(protocol_lst.forget_abi()),
+            amount_in: amount,
+        });
+        Ok(LstResponse::RequestSent { request_id })
+    }
+
+    /// Emits `event` on the application's event stream.
+    fn emit(&mut self, event: LstEvent) {
+        self.runtime.emit(StreamName::from(LST_STREAM_NAME), &event);
+    }
+
+    /// Sends a user request to the stake chain, tracked so that it bounces back if rejected.
+    fn send_request(&mut self, message: Message) {
+        let dest_chain_id = self.get_app_chain_id();
+        self.runtime.prepare_message(message).with_authentication().with_tracking().send_to(dest_chain_id);
+    }
+
+    fn is_protocol_lst(&mut self, token_id: &ApplicationId) -> bool {
+        *token_id == self.runtime.application_parameters().get_protocol_lst().forget_abi()
+    }
+
+    /// Returns the native value of `amount` of `token_id`.
+    ///
+    /// Protocol lst is priced by the pool, so callers must retire the redeemed shares with
+    /// `remove_from_pool` once the conversion goes ahead.
+    async fn native_for_amount(&mut self, token_id: ApplicationId, amount: Amount) -> Amount {
+        if self.is_protocol_lst(&token_id) {
+            self.state.native_for_shares(amount)
+        } else {
+            lst_to_native(amount, self.state.exchange_rate(&token_id).await)
+        }
+    }
+
+    /// Returns the amount of `token_id` that `native` buys.
+    ///
+    /// Protocol lst is priced by the pool, so callers must issue the shares with `add_to_pool`
+    /// once the conversion goes ahead.
+    async fn amount_for_native(&mut self, token_id: ApplicationId, native: Amount) -> Amount {
+        if self.is_protocol_lst(&token_id) {
+            self.state.shares_for_native(native)
+        } else {
+            native_to_lst(native, self.state.exchange_rate(&token_id).await)
+        }
+    }
     // fn staked_token_app_id(&mut self) -> ApplicationId<FungibleTokenAbi> {
     //     self.runtime.application_parameters().tokens[1]
     // }
@@ -229,7 +810,7 @@ impl LstContract {
     async fn stake_from_local_account(&mut self, owner: AccountOwner, amount: Amount) {
         assert!(amount > Amount::ZERO, "Pledge is empty");
         let fungible_id = self.native_token_app_id();
-        self.receive_from_user(owner, amount, fungible_id);
+        self.receive_from_user(owner, amount, fungible_id, None);
     }
     /// Transfers `amount` tokens from the funds in custody to the `owner`'s account.
     fn send_to_user(&mut self, amount: Amount, user: AccountOwner, fungible_id: ApplicationId<FungibleTokenAbi>, user_chain_id: ChainId) {
@@ -244,16 +825,163 @@ impl LstContract {
         self.runtime.call_application(true, fungible_id, &transfer);
     }
 
+    /// Transfers `amount` native tokens from the vault to the `user`'s account.
+    fn send_native_to_user(&mut self, amount: Amount, user: AccountOwner, user_chain_id: ChainId) {
+        let app_owner: AccountOwner = self.runtime.application_id().into();
+        self.runtime.transfer(app_owner, Account { chain_id: user_chain_id, owner: user }, amount);
+    }
+
     /// Calls into the Fungible Token application to receive tokens from the given account.
-    fn receive_from_user(&mut self, owner: AccountOwner, amount: Amount, fungible_id: ApplicationId<FungibleTokenAbi>) {
-        let app_owner = self.runtime.application_id().into();
+    ///
+    /// The tokens pass through the application's account on the current chain, so that if the
+    /// transfer to the stake chain bounces they come back under the application's custody. When an
+    /// operator acts for `owner`, they are pulled with an allowance `owner` approved for the
+    /// application. The
+    /// transfer into the vault is tagged with the memo of `request_id`, if any.
+    fn receive_from_user(&mut self, owner: AccountOwner, amount: Amount, fungible_id: ApplicationId<FungibleTokenAbi>, request_id: Option<u64>) {
+        let app_owner: AccountOwner = self.runtime.application_id().into();
 
-        let target_account = FungibleAccount {
+        let custody_account = FungibleAccount {
+            chain_id: self.runtime.chain_id(),
+            owner: app_owner,
+        };
+        let transfer = if self.runtime.check_account_permission(owner).is_ok() {
+            fungible::Operation::Transfer {
+                owner,
+                amount,
+                target_account: custody_account,
+            }
+        } else {
+            // an operator submitted the request, so the tokens are pulled with the allowance the
+            // owner gave this application
+            fungible::Operation::TransferFrom {
+                owner,
+                spender: app_owner,
+                amount,
+                target_account: custody_account,
+            }
+        };
+        self.runtime.call_application(/* authenticated by owner */ true, fungible_id, &transfer);
+
+        let vault_account = FungibleAccount {
             chain_id: self.runtime.application_creator_chain_id(),
             owner: app_owner,
         };
-        let transfer = fungible::Operation::Transfer { owner, amount, target_account };
-        self.runtime.call_application(false, fungible_id, &transfer);
+        let transfer = fungible::Operation::TransferWithMemo {
+            owner: app_owner,
+            amount,
+            target_account: vault_account,
+            memo: request_id.map(request_memo),
+        };
+        self.runtime.call_application(true, fungible_id, &transfer);
+    }
+
+    /// Returns the input of a request rejected by the stake chain to the user.
+    ///
+    /// Runs on the user chain, where the bounced input transfer has been credited back to the
+    /// application's account.
+    fn refund_bounced_message(&mut self, message: Message) {
+        let user_chain_id = self.runtime.chain_id();
+        match message {
+            Message::StakeNative { user, amount, request_id, .. } => {
+                self.refund(user, user_chain_id, request_id, None, amount);
+            }
+            Message::StakeLst {
+                user,
+                amount_in,
+                lst_type_in,
+                request_id,
+                ..
+            }
+            | Message::Swap {
+                user,
+                amount_in,
+                lst_type_in,
+                request_id,
+                ..
+            } => {
+                self.refund(user, user_chain_id, request_id, Some(lst_type_in), amount_in);
+            }
+            Message::Unstake { user, amount, request_id, .. } => {
+                let protocol_lst = self.runtime.application_parameters().get_protocol_lst();
+                self.refund(user, user_chain_id, request_id, Some(protocol_lst.forget_abi()), amount);
+            }
+            Message::StakeLocalAccount { .. } | Message::ClaimWithdrawal { .. } | Message::SetPaused { .. } => {}
+        }
+    }
+
+    /// Returns whether the vault can pay out `amount` of `token_id`.
+    ///
+    /// Protocol lst is minted on demand, so only other lsts are limited by the reserves.
+    async fn can_pay_out(&mut self, token_id: &ApplicationId, amount: Amount) -> bool {
+        self.is_protocol_lst(token_id) || self.state.reserve(token_id).await >= amount
+    }
+
+    /// Pays `amount_out` of `token_id` to the user of a request, keeping `fee` in the vault for
+    /// the treasury.
+    async fn pay_out(&mut self, token_id: ApplicationId, amount_out: Amount, fee: Amount, user: AccountOwner, user_chain_id: ChainId) {
+        if self.is_protocol_lst(&token_id) {
+            let vault = self.runtime.application_id().into();
+            let chain_id = self.runtime.chain_id();
+            self.mint_protocol_lst(fee, vault, chain_id);
+            self.mint_protocol_lst(amount_out, user, user_chain_id);
+        } else {
+            self.state.remove_reserves(token_id, amount_out.saturating_add(fee)).await;
+            self.state.record_payout(token_id, user, amount_out).await;
+            self.send_to_user(amount_out, user, token_id.with_abi::<FungibleTokenAbi>(), user_chain_id);
+        }
+        self.state.add_fee(token_id, fee).await;
+    }
+
+    /// Takes `amount` of `token_id`, already transferred to the vault, out of circulation.
+    ///
+    /// Protocol lst is burned, while other lsts are added to the reserves.
+    async fn take_in(&mut self, token_id: ApplicationId, amount: Amount) {
+        if self.is_protocol_lst(&token_id) {
+            self.burn_protocol_lst(amount);
+        } else {
+            self.state.add_reserves(token_id, amount).await;
+        }
+    }
+
+    /// Mints `amount` protocol lst to the `owner`'s account on `chain_id`.
+    ///
+    /// The application must have been made the minter of the protocol lst on this chain.
+    fn mint_protocol_lst(&mut self, amount: Amount, owner: AccountOwner, chain_id: ChainId) {
+        if amount == Amount::ZERO {
+            return;
+        }
+        let mint = fungible::Operation::Mint {
+            target_account: FungibleAccount { chain_id, owner },
+            amount,
+        };
+        let protocol_lst = self.native_token_app_id();
+        self.runtime.call_application(true, protocol_lst, &mint);
+    }
+
+    /// Burns `amount` protocol lst held by the vault.
+    fn burn_protocol_lst(&mut self, amount: Amount) {
+        let burn = fungible::Operation::Burn {
+            owner: self.runtime.application_id().into(),
+            amount,
+        };
+        let protocol_lst = self.native_token_app_id();
+        self.runtime.call_application(true, protocol_lst, &burn);
+    }
+
+    /// Returns `amount` of `lst_type`, or native tokens if `None`, to the user of a request.
+    fn refund(&mut self, user: AccountOwner, user_chain_id: ChainId, request_id: u64, lst_type: Option<ApplicationId>, amount: Amount) {
+        match lst_type {
+            Some(token_id) => self.send_to_user(amount, user, token_id.with_abi::<FungibleTokenAbi>(), user_chain_id),
+            None => self.send_native_to_user(amount, user, user_chain_id),
+        }
+        self.emit(LstEvent::Refunded {
+            user,
+            user_chain_id,
+            request_id,
+            lst_type,
+            amount,
+        });
     }
 
     // /// Calls into the Fungible Token application to receive tokens from the given account.
@@ -285,15 +1013,15 @@ impl LstContract {
 mod tests {
     use std::str::FromStr;
 
+    use fungible::{FungibleTokenAbi, Parameters};
     use futures::FutureExt as _;
     use linera_sdk::{
-        abis::fungible::{self, FungibleTokenAbi, Parameters},
         linera_base_types::{AccountOwner, AccountSecretKey, Amount, ApplicationId, ChainId, Secp256k1SecretKey},
         util::BlockingWait,
         views::View,
         Contract, ContractRuntime,
     };
-    use lst::{LstAbi, Operation};
+    use lst::{LstAbi, LstError, LstResponse, Operation};
 
     use super::{LstContract, LstState};
 
@@ -324,6 +1052,59 @@ mod tests {
 
         //     assert_eq!(response, ());
     }
+
+    #[test]
+    fn operator_acts_for_user() {
+        let mut lst = create_lst();
+        let user = AccountOwner::from(AccountSecretKey::Secp256k1(Secp256k1SecretKey::generate()).public());
+        let operator = AccountOwner::from(AccountSecretKey::Secp256k1(Secp256k1SecretKey::generate()).public());
+
+        lst.runtime.set_authenticated_signer(Some(user));
+        let response = lst.execute_operation(Operation::SetOperator { operator, approved: true }).blocking_wait();
+        assert_eq!(response, Ok(LstResponse::Ok));
+
+        lst.runtime.set_authenticated_signer(Some(operator));
+        assert_eq!(lst.check_user(user).blocking_wait(), Ok(()));
+        // operators can't move native tokens, which need the user's signature
+        assert_eq!(lst.check_owner(user), Err(LstError::Unauthorized(user)));
+
+        lst.runtime.set_authenticated_signer(Some(user));
+        let response = lst.execute_operation(Operation::SetOperator { operator, approved: false }).blocking_wait();
+        assert_eq!(response, Ok(LstResponse::Ok));
+
+        lst.runtime.set_authenticated_signer(Some(operator));
+        assert_eq!(lst.check_user(user).blocking_wait(), Err(LstError::Unauthorized(user)));
+    }
+
+    #[test]
+    fn undelegated_signer_is_rejected() {
+        let mut lst = create_lst();
+        let user = AccountOwner::from(AccountSecretKey::Secp256k1(Secp256k1SecretKey::generate()).public());
+        let admin = AccountOwner::from(AccountSecretKey::Secp256k1(Secp256k1SecretKey::generate()).public());
+        lst.state.admin.set(Some(admin));
+
+        // the admin can only approve operators for its own account
+        lst.runtime.set_authenticated_signer(Some(admin));
+        let response = lst.execute_operation(Operation::SetOperator { operator: admin, approved: true }).blocking_wait();
+        assert_eq!(response, Ok(LstResponse::Ok));
+        assert_eq!(lst.check_user(user).blocking_wait(), Err(LstError::Unauthorized(user)));
+
+        lst.runtime.set_authenticated_signer(None);
+        let response = lst.execute_operation(Operation::SetOperator { operator: admin, approved: true }).blocking_wait();
+        assert_eq!(response, Err(LstError::NotSigned));
+    }
+
+    fn create_lst() -> LstContract {
+        let protocol_lst = ApplicationId::default().with_abi::<FungibleTokenAbi>();
+        let params = lst::Parameters { protocol_lst };
+
+        let mut runtime = ContractRuntime::new().with_application_parameters(params);
+        runtime.set_chain_id(ChainId::default());
+        runtime.set_application_id(ApplicationId::default().with_abi::<LstAbi>());
+        runtime.set_authenticated_caller_id(None);
+        let state = LstState::load(runtime.root_view_storage_context()).blocking_wait().expect("Failed to read from mock key value store");
+        LstContract { state, runtime }
+    }
 }
 // #[test]
 // #[should_panic(expected = "Lst application doesn't support any cross-chain messages")]
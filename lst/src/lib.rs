@@ -1,8 +1,10 @@
-use async_graphql::{scalar, Request, Response};
+use std::fmt;
+
+use async_graphql::{scalar, Enum, InputObject, Request, Response, SimpleObject};
 use fungible::FungibleTokenAbi;
 use linera_sdk::{
     graphql::GraphQLMutationRoot,
-    linera_base_types::{AccountOwner, Amount, ApplicationId, ChainId, ContractAbi, ServiceAbi},
+    linera_base_types::{Account, AccountOwner, Amount, ApplicationId, ChainId, ContractAbi, ServiceAbi, TimeDelta},
 };
 use serde::{Deserialize, Serialize};
 
@@ -10,7 +12,7 @@ pub struct LstAbi;
 
 impl ContractAbi for LstAbi {
     type Operation = Operation;
-    type Response = ();
+    type Response = Result<LstResponse, LstError>;
 }
 
 impl ServiceAbi for LstAbi {
@@ -20,6 +22,8 @@ impl ServiceAbi for LstAbi {
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub struct Parameters {
+    /// The fungible token minted on stake and burned on unstake. It must be created mintable, and
+    /// the lst application set as its minter on the stake chain.
     pub protocol_lst: ApplicationId<FungibleTokenAbi>,
 }
 impl Parameters {
@@ -30,15 +34,463 @@ impl Parameters {
 
 scalar!(Parameters);
 
+/// The arguments used to create the LST application.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct InstantiationArgument {
+    /// The account allowed to change the application configuration.
+    pub admin: AccountOwner,
+    /// How long unstaked native tokens stay locked before they can be claimed.
+    pub withdrawal_cooldown: TimeDelta,
+}
+
+/// Where the exchange rate of an LST comes from.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum RateSource {
+    /// Set by the admin with `Operation::UpdateExchangeRate`.
+    Admin,
+    /// Derived from the pooled native tokens, only used by the protocol LST.
+    Pool,
+}
+
+/// How the application handles an approved LST.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, SimpleObject, InputObject)]
+#[graphql(input_name = "LstConfigInput")]
+pub struct LstConfig {
+    pub ticker: String,
+    pub rate_source: RateSource,
+    /// Maximum amount of the LST the vault accepts, unlimited when `None`.
+    pub deposit_cap: Option<Amount>,
+    /// Maximum amount of the LST a single user can have deposited, unlimited when `None`.
+    pub user_deposit_limit: Option<Amount>,
+    /// Fee charged on stakes of the LST, or of native tokens for it, in basis points.
+    pub stake_fee_bps: u16,
+    /// Fee charged on swaps into the LST, in basis points.
+    pub swap_fee_bps: u16,
+    /// Whether native tokens can be staked directly for the LST.
+    pub native_staking: bool,
+    /// Disabled LSTs stay listed but can't be staked or swapped.
+    pub enabled: bool,
+}
+
+impl LstConfig {
+    /// Returns an enabled, admin-priced configuration without caps or fees.
+    pub fn new(ticker: impl Into<String>) -> Self {
+        LstConfig {
+            ticker: ticker.into(),
+            rate_source: RateSource::Admin,
+            deposit_cap: None,
+            user_deposit_limit: None,
+            stake_fee_bps: 0,
+            swap_fee_bps: 0,
+            native_staking: true,
+            enabled: true,
+        }
+    }
+}
+
+/// How much pooled native is kept liquid for instant unstakes, and what they cost.
+///
+/// The fee grows linearly from `min_fee_bps` when an exit leaves the buffer full to
+/// `max_fee_bps` when it empties it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, SimpleObject, InputObject)]
+#[graphql(input_name = "InstantUnstakeConfigInput")]
+pub struct InstantUnstakeConfig {
+    pub buffer_target: Amount,
+    pub min_fee_bps: u16,
+    pub max_fee_bps: u16,
+}
+
+/// A user action that can be paused.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum LstAction {
+    Stake,
+    Swap,
+    Unstake,
+}
+
+/// Which user actions are halted, either for the whole application or for a single LST.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, SimpleObject, InputObject)]
+#[graphql(input_name = "PauseFlagsInput")]
+pub struct PauseFlags {
+    pub stake: bool,
+    pub swap: bool,
+    pub unstake: bool,
+}
+
+impl PauseFlags {
+    /// Returns whether `action` is halted by these flags.
+    pub fn is_paused(&self, action: LstAction) -> bool {
+        match action {
+            LstAction::Stake => self.stake,
+            LstAction::Swap => self.swap,
+            LstAction::Unstake => self.unstake,
+        }
+    }
+}
+
+/// The outcome of a successful LST operation.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum LstResponse {
+    Ok,
+    /// The request was sent to the stake chain, which settles it asynchronously.
+    RequestSent {
+        request_id: u64,
+    },
+    /// The amount the quoted conversion would currently return.
+    Quote {
+        amount_out: Amount,
+    },
+}
+
+/// The reason an LST operation was rejected.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum LstError {
+    /// The operation must be signed by the application admin.
+    NotAdmin,
+    /// The operation must be signed by the oracle.
+    NotOracle,
+    /// Rewards were already reported for this or a later epoch.
+    StaleEpoch(u64),
+    /// The reported rewards raise the pooled native tokens by more than the bound of the epochs
+    /// elapsed since the last report.
+    RewardTooLarge,
+    /// Losses must be positive and leave a positive exchange rate.
+    InvalidLoss,
+    /// Instant unstake fees must not decrease with usage or exceed 100%.
+    InvalidInstantUnstakeConfig,
+    /// The operation must be signed.
+    NotSigned,
+    /// The operation debits an account that didn't sign it or approve its signer as an operator.
+    Unauthorized(AccountOwner),
+    /// The amount must be greater than zero.
+    ZeroAmount,
+    /// Exchange rates must be greater than zero.
+    InvalidExchangeRate,
+    /// The protocol LST is priced by the pool and has no fixed exchange rate.
+    ProtocolLstRate,
+    /// A swap must exchange two different LSTs.
+    SameLstType,
+    /// The LST is not approved by the application, or is disabled.
+    LstNotApproved(ApplicationId),
+    /// The LST is already listed, use `Operation::UpdateLst` to change it.
+    LstAlreadyListed(ApplicationId),
+    /// Native tokens can't be staked directly for the LST.
+    NativeStakingNotAllowed(ApplicationId),
+    /// The vault already holds as much of the LST as its deposit cap allows.
+    DepositCapExceeded(ApplicationId),
+    /// The user already deposited as much of the LST as the per-user limit allows.
+    UserDepositLimitExceeded(ApplicationId),
+    /// The vault holds too little of the LST to pay out the request.
+    InsufficientReserves(ApplicationId),
+    /// The treasury holds less than the requested amount of fees.
+    InsufficientFees(ApplicationId),
+    /// The vault holds too few native tokens to back the redemption.
+    InsufficientNativeLiquidity,
+    /// Fees must not exceed 100%, and only the protocol LST is priced by the pool.
+    InvalidLstConfig,
+    /// The protocol LST can be disabled but not delisted.
+    ProtocolLstDelist,
+    /// The LST can't be delisted while the vault holds reserves, deposits or fees of it.
+    LstHasFunds(ApplicationId),
+    /// The owner has no withdrawal request with this id.
+    WithdrawalNotFound(u64),
+    /// The withdrawal request is still cooling down.
+    WithdrawalNotMatured(u64),
+    /// The action is paused, globally or for one of the LSTs involved.
+    Paused(LstAction),
+}
+
+impl fmt::Display for LstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LstError::NotAdmin => write!(f, "Only the admin can change the configuration"),
+            LstError::NotOracle => write!(f, "Only the oracle can report rewards"),
+            LstError::StaleEpoch(epoch) => write!(f, "Rewards for epoch {epoch} are already reported"),
+            LstError::RewardTooLarge => write!(f, "Reported rewards exceed the per-epoch bound"),
+            LstError::InvalidLoss => write!(f, "Invalid loss report"),
+            LstError::InvalidInstantUnstakeConfig => write!(f, "Invalid instant unstake configuration"),
+            LstError::NotSigned => write!(f, "Operation must be signed"),
+            LstError::Unauthorized(owner) => write!(f, "Not authorized to debit {owner}"),
+            LstError::ZeroAmount => write!(f, "Amount must be greater than zero"),
+            LstError::InvalidExchangeRate => write!(f, "Exchange rate must be positive"),
+            LstError::ProtocolLstRate => write!(f, "Protocol lst rate is derived from the pool"),
+            LstError::SameLstType => write!(f, "Lst type in and out must differ"),
+            LstError::LstNotApproved(token_id) => write!(f, "Lst {token_id} is not approved"),
+            LstError::LstAlreadyListed(token_id) => write!(f, "Lst {token_id} is already listed"),
+            LstError::NativeStakingNotAllowed(token_id) => write!(f, "Native staking is not allowed for lst {token_id}"),
+            LstError::DepositCapExceeded(token_id) => write!(f, "Deposit cap of lst {token_id} exceeded"),
+            LstError::UserDepositLimitExceeded(token_id) => write!(f, "User deposit limit of lst {token_id} exceeded"),
+            LstError::InsufficientReserves(token_id) => write!(f, "Not enough reserves of lst {token_id} in the vault"),
+            LstError::InsufficientFees(token_id) => write!(f, "Not enough fees of lst {token_id} in the treasury"),
+            LstError::InsufficientNativeLiquidity => write!(f, "Not enough native tokens in the vault"),
+            LstError::InvalidLstConfig => write!(f, "Invalid lst configuration"),
+            LstError::ProtocolLstDelist => write!(f, "Protocol lst can't be delisted"),
+            LstError::LstHasFunds(token_id) => write!(f, "Lst {token_id} still has funds in the vault"),
+            LstError::WithdrawalNotFound(id) => write!(f, "Withdrawal request {id} not found"),
+            LstError::WithdrawalNotMatured(id) => write!(f, "Withdrawal request {id} is still cooling down"),
+            LstError::Paused(action) => write!(f, "{action:?} is paused"),
+        }
+    }
+}
+
+impl std::error::Error for LstError {}
+
+/// Name of the stream the LST application emits its [`LstEvent`]s on.
+pub const LST_STREAM_NAME: &[u8] = b"lst_events";
+
+/// A change to the LST application, emitted for indexers.
+///
+/// Native tokens are represented by `None` where an event can refer to either an LST or native.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum LstEvent {
+    /// A user request left the user chain for the stake chain.
+    RequestSent {
+        user: AccountOwner,
+        request_id: u64,
+        lst_type_in: Option<ApplicationId>,
+        amount_in: Amount,
+    },
+    /// Native tokens or an LST were staked for an LST.
+    Staked {
+        user: AccountOwner,
+        user_chain_id: ChainId,
+        request_id: u64,
+        lst_type_in: Option<ApplicationId>,
+        amount_in: Amount,
+        lst_type_out: ApplicationId,
+        amount_out: Amount,
+        /// Amount of `lst_type_out` kept by the treasury.
+        fee: Amount,
+    },
+    /// Protocol LST was redeemed and the native tokens queued for withdrawal.
+    Unstaked {
+        user: AccountOwner,
+        user_chain_id: ChainId,
+        request_id: u64,
+        shares: Amount,
+        native_amount: Amount,
+        withdrawal_id: u64,
+    },
+    /// Protocol LST was redeemed and paid out right away from the liquidity buffer.
+    InstantUnstaked {
+        user: AccountOwner,
+        user_chain_id: ChainId,
+        request_id: u64,
+        shares: Amount,
+        native_amount: Amount,
+        /// Native tokens kept in the pool for the remaining holders.
+        fee: Amount,
+    },
+    /// A matured withdrawal was paid out.
+    WithdrawalClaimed {
+        owner: AccountOwner,
+        chain_id: ChainId,
+        withdrawal_id: u64,
+        amount: Amount,
+    },
+    /// An LST was swapped for another.
+    Swapped {
+        user: AccountOwner,
+        user_chain_id: ChainId,
+        request_id: u64,
+        lst_type_in: ApplicationId,
+        amount_in: Amount,
+        lst_type_out: ApplicationId,
+        amount_out: Amount,
+        /// Amount of `lst_type_out` kept by the treasury.
+        fee: Amount,
+    },
+    /// The input of a request was returned to the user.
+    Refunded {
+        user: AccountOwner,
+        user_chain_id: ChainId,
+        request_id: u64,
+        lst_type: Option<ApplicationId>,
+        amount: Amount,
+    },
+    /// An LST was approved.
+    LstListed { token_id: ApplicationId, config: LstConfig },
+    /// The configuration of a listed LST was changed.
+    LstUpdated { token_id: ApplicationId, config: LstConfig },
+    /// An LST was removed from the registry.
+    LstDelisted { token_id: ApplicationId },
+    /// The exchange rate of an LST was changed.
+    RateUpdated { token_id: ApplicationId, rate: Amount },
+    /// The oracle and the bounds on its reward reports were changed.
+    OracleConfigured { oracle: AccountOwner, max_reward_bps: u16, epoch_duration: TimeDelta },
+    /// Staking rewards were added to the pooled native tokens.
+    RewardsReported { epoch: u64, amount: Amount, protocol_lst_price: Amount },
+    /// A loss was reported on an LST, or on the pooled native tokens when `token_id` is `None`.
+    LossReported {
+        token_id: Option<ApplicationId>,
+        loss_bps: u16,
+        /// Native tokens removed from the pool, shared by all protocol LST holders.
+        native_loss: Amount,
+        protocol_lst_price: Amount,
+    },
+    /// The liquidity buffer target or the instant unstake fees were changed.
+    InstantUnstakeConfigured { config: InstantUnstakeConfig, liquidity_buffer: Amount },
+    /// LST liquidity was added to the vault reserves.
+    LiquidityProvided { token_id: ApplicationId, amount: Amount },
+    /// Native tokens were added to the vault to back protocol LST redemptions.
+    NativeLiquidityProvided { amount: Amount },
+    /// Accumulated fees were paid out of the treasury.
+    FeesWithdrawn { token_id: ApplicationId, amount: Amount, target_account: Account },
+    /// The pause flags were changed, globally when `token_id` is `None`.
+    PauseUpdated { token_id: Option<ApplicationId>, flags: PauseFlags },
+    /// A user approved or revoked an operator on their chain.
+    OperatorUpdated { owner: AccountOwner, operator: AccountOwner, approved: bool },
+}
+
+/// Converts an amount of native tokens into the amount of an LST it buys at `rate`.
+///
+/// `rate` is the number of native tokens one LST is worth, using the same fixed-point
+/// precision as `Amount` (i.e. `Amount::ONE` is 1:1).
+pub fn native_to_lst(native: Amount, rate: Amount) -> Amount {
+    mul_div(native, Amount::ONE, rate)
+}
+
+/// Converts an amount of an LST into the amount of native tokens it is worth at `rate`.
+pub fn lst_to_native(lst: Amount, rate: Amount) -> Amount {
+    mul_div(lst, rate, Amount::ONE)
+}
+
+/// Returns the memo tagging the transfers of request `request_id` into the vault: the id as
+/// little-endian bytes.
+pub fn request_memo(request_id: u64) -> Vec<u8> {
+    request_id.to_le_bytes().to_vec()
+}
+
+/// Returns the fee of `fee_bps` basis points charged on `amount`, rounding down.
+pub fn fee_for(amount: Amount, fee_bps: u16) -> Amount {
+    mul_div(amount, Amount::from_attos(fee_bps.into()), Amount::from_attos(10_000))
+}
+
+/// Computes `value * numerator / denominator`, rounding down.
+///
+/// The product is computed on 256 bits, so only a quotient that doesn't fit in an `Amount`
+/// overflows.
+pub fn mul_div(value: Amount, numerator: Amount, denominator: Amount) -> Amount {
+    let denominator = u128::from(denominator);
+    assert!(denominator > 0, "Division by zero amount");
+    let (high, low) = wide_mul(u128::from(value), u128::from(numerator));
+    assert!(high < denominator, "Amount overflow");
+
+    // long division of the 256-bit product, one bit at a time
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+    Amount::from_attos(quotient)
+}
+
+/// Returns the high and low halves of the 256-bit product `a * b`.
+fn wide_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+    let low_low = a_low * b_low;
+    let low_high = a_low * b_high;
+    let high_low = a_high * b_low;
+    let middle = (low_low >> 64) + (low_high & MASK) + (high_low & MASK);
+    let low = (low_low & MASK) | (middle << 64);
+    let high = a_high * b_high + (low_high >> 64) + (high_low >> 64) + (middle >> 64);
+    (high, low)
+}
+
 #[derive(Debug, Deserialize, Serialize, GraphQLMutationRoot)]
 pub enum Operation {
+    /// Lists a new LST with its configuration.
     NewLst {
         token_id: ApplicationId,
+        config: LstConfig,
+    },
+    /// Replaces the configuration of a listed LST.
+    UpdateLst {
+        token_id: ApplicationId,
+        config: LstConfig,
+    },
+    /// Removes an LST from the registry, once the vault holds no reserves, deposits or fees of
+    /// it. Its exchange rate is kept in case it is listed again.
+    DelistLst {
+        token_id: ApplicationId,
+    },
+    /// Sets how many native tokens one unit of `token_id` is worth.
+    UpdateExchangeRate {
+        token_id: ApplicationId,
+        rate: Amount,
+    },
+    /// Pauses or resumes user actions, globally when `token_id` is `None`.
+    ///
+    /// The flags are enforced on the stake chain and forwarded to `chains`, so that operations there
+    /// are rejected before any funds move.
+    SetPaused {
+        token_id: Option<ApplicationId>,
+        flags: PauseFlags,
+        chains: Vec<ChainId>,
+    },
+    /// Sets the account allowed to report rewards, how much one epoch of rewards can raise the
+    /// pooled native tokens, in basis points, and how long an epoch lasts.
+    ConfigureOracle {
+        oracle: AccountOwner,
+        max_reward_bps: u16,
+        /// Reports after a gap may catch up on as many epochs as fit in the time since the last
+        /// report, and on a single epoch when zero.
+        epoch_duration: TimeDelta,
+    },
+    /// Adds the staking rewards of `epoch` to the pooled native tokens, raising the protocol LST
+    /// price. The rewarded native tokens are expected to be deposited into the vault separately,
+    /// with `ProvideNativeLiquidity`.
+    ReportRewards {
+        amount: Amount,
+        epoch: u64,
+    },
+    /// Reports that `token_id`, or the pooled native tokens when `None`, lost `loss_bps` basis
+    /// points of its value. An LST loss lowers its exchange rate and the pooled native tokens
+    /// backed by it, and `pause` halts staking and swapping it, or every LST when `None`.
+    ReportLoss {
+        token_id: Option<ApplicationId>,
+        loss_bps: u16,
+        pause: bool,
+    },
+    /// Sets the liquidity buffer target and the instant unstake fees.
+    ConfigureInstantUnstake {
+        config: InstantUnstakeConfig,
     },
+    /// Moves `amount` of `token_id` from `owner` into the vault reserves. Protocol LST is minted
+    /// on demand and needs none.
+    ProvideLiquidity {
+        owner: AccountOwner,
+        token_id: ApplicationId,
+        amount: Amount,
+    },
+    /// Moves `amount` native tokens from `owner` into the vault, backing protocol LST redemptions
+    /// of pooled value that didn't come in as native tokens, e.g. rewards or staked LSTs.
+    ProvideNativeLiquidity {
+        owner: AccountOwner,
+        amount: Amount,
+    },
+    /// Pays `amount` of the fees collected in `token_id` out of the treasury.
+    WithdrawFees {
+        token_id: ApplicationId,
+        amount: Amount,
+        target_account: Account,
+    },
+    /// Stakes native tokens of `user`, who must sign the operation: operators can't move native
+    /// tokens.
     StakeNative {
         user: AccountOwner,
         amount: Amount,
         lst_type_out: ApplicationId,
+        /// The stake is refunded if it would return less than this.
+        min_amount_out: Amount,
     },
     StakeLst {
         user: AccountOwner,
@@ -50,11 +502,38 @@ pub enum Operation {
         owner: AccountOwner,
         amount: Amount,
     },
+    /// Unstakes and pays out native tokens right away minus a fee, or queues the withdrawal like
+    /// `Unstake` if the liquidity buffer is too small.
+    InstantUnstake {
+        owner: AccountOwner,
+        amount: Amount,
+    },
+    /// Pays out a matured withdrawal request to its owner.
+    ClaimWithdrawal {
+        owner: AccountOwner,
+        request_id: u64,
+    },
     Swap {
         user: AccountOwner,
         amount_in: Amount,
         lst_type_in: ApplicationId,
         lst_type_out: ApplicationId,
+        /// The swap is refunded if it would return less than this.
+        min_amount_out: Amount,
+    },
+    /// Approves or revokes `operator` to stake, swap and unstake on behalf of the signer on this
+    /// chain. The signer must also approve this application as a spender of the tokens involved.
+    SetOperator {
+        operator: AccountOwner,
+        approved: bool,
+    },
+    /// Returns how much `lst_type_out` `amount_in` of `lst_type_in` (or native tokens if
+    /// `None`) currently buys after fees, priced as a swap or a native stake. Only meaningful on
+    /// the stake chain.
+    Quote {
+        amount_in: Amount,
+        lst_type_in: Option<ApplicationId>,
+        lst_type_out: ApplicationId,
     },
     Test,
 }
@@ -70,11 +549,15 @@ pub enum Message {
         amount: Amount,
         lst_type_out: ApplicationId,
         user_chain_id: ChainId,
+        min_amount_out: Amount,
+        request_id: u64,
     },
     StakeLst {
         user: AccountOwner,
         amount_in: Amount,
         user_chain_id: ChainId,
+        lst_type_in: ApplicationId,
+        request_id: u64,
     },
     Swap {
         user: AccountOwner,
@@ -82,5 +565,24 @@ pub enum Message {
         user_chain_id: ChainId,
         lst_type_in: ApplicationId,
         lst_type_out: ApplicationId,
+        min_amount_out: Amount,
+        request_id: u64,
+    },
+    Unstake {
+        user: AccountOwner,
+        amount: Amount,
+        user_chain_id: ChainId,
+        request_id: u64,
+        /// Pay out from the liquidity buffer if it can cover the request.
+        instant: bool,
+    },
+    ClaimWithdrawal {
+        owner: AccountOwner,
+        request_id: u64,
+    },
+    /// Mirrors the pause flags of the stake chain on a user chain.
+    SetPaused {
+        token_id: Option<ApplicationId>,
+        flags: PauseFlags,
     },
 }
@@ -1,14 +1,460 @@
-use async_graphql::SimpleObject;
+use std::collections::{BTreeMap, BTreeSet};
+
+use async_graphql::{ComplexObject, SimpleObject};
 
 use linera_sdk::{
-    linera_base_types::{AccountOwner, Amount, ApplicationId},
-    views::{linera_views, MapView, RootView, SetView, ViewStorageContext},
+    linera_base_types::{AccountOwner, Amount, ApplicationId, ChainId, TimeDelta, Timestamp},
+    views::{linera_views, LogView, MapView, RegisterView, RootView, ViewStorageContext},
 };
+use lst::{fee_for, mul_div, InstantUnstakeConfig, LstAction, LstConfig, LstError, PauseFlags};
+use serde::{Deserialize, Serialize};
 
 #[derive(RootView, SimpleObject)]
+#[graphql(complex)]
 #[view(context = "ViewStorageContext")]
 pub struct LstState {
-    pub stake_balances: MapView<AccountOwner, Amount>,
-    pub approved_lst_set: SetView<ApplicationId>,
-    pub lst_with_native_stake: SetView<ApplicationId>,
+    /// The account allowed to change the application configuration.
+    pub admin: RegisterView<Option<AccountOwner>>,
+    /// Configuration of every approved LST, including the protocol LST.
+    pub lst_configs: MapView<ApplicationId, LstConfig>,
+    /// Amount of each LST in the vault that can be paid out to users, excluding the treasury.
+    pub reserves: MapView<ApplicationId, Amount>,
+    /// Fees collected in each LST, held in the vault until the admin withdraws them.
+    pub treasury: MapView<ApplicationId, Amount>,
+    /// Amount of each LST deposited into the vault by users and not paid out since.
+    pub lst_deposits: MapView<ApplicationId, Amount>,
+    /// Amount of each LST whose native value was added to the pool, by staking it or swapping it
+    /// for protocol LST, and not paid back out for protocol LST since.
+    pub pool_backing: MapView<ApplicationId, Amount>,
+    /// Amount of each LST deposited by each user and not paid back to them since.
+    pub user_deposits: MapView<AccountOwner, BTreeMap<ApplicationId, Amount>>,
+    /// Accounts each user approved to submit requests on their behalf from this chain.
+    pub operators: MapView<AccountOwner, BTreeSet<AccountOwner>>,
+    /// Native tokens per unit of each LST, with `Amount` precision. Missing entries are 1:1.
+    pub exchange_rates: MapView<ApplicationId, Amount>,
+    /// Native tokens backing the protocol LST, including reported rewards.
+    pub total_pooled_native: RegisterView<Amount>,
+    /// Outstanding protocol LST, i.e. shares of `total_pooled_native`.
+    pub total_shares: RegisterView<Amount>,
+    /// Native tokens held by the vault that can pay out protocol LST redemptions. Pooled value
+    /// backed by LST deposits or unfunded rewards is not included.
+    pub native_liquidity: RegisterView<Amount>,
+    /// Target size and fees of the liquidity buffer.
+    pub instant_unstake: RegisterView<InstantUnstakeConfig>,
+    /// Pooled native tokens kept liquid for instant unstakes, refilled by native stakes.
+    pub liquidity_buffer: RegisterView<Amount>,
+    /// How long unstaked native tokens stay locked before they can be claimed.
+    pub withdrawal_cooldown: RegisterView<TimeDelta>,
+    pub next_withdrawal_id: RegisterView<u64>,
+    /// Id of the next request sent from this chain to the stake chain.
+    pub next_request_id: RegisterView<u64>,
+    /// Pending withdrawal requests of each user.
+    pub withdrawal_requests: MapView<AccountOwner, Vec<WithdrawalRequest>>,
+    /// The account allowed to report staking rewards.
+    pub oracle: RegisterView<Option<AccountOwner>>,
+    /// How much one epoch of rewards can raise the pooled native tokens, in basis points.
+    pub max_reward_bps: RegisterView<u16>,
+    /// How long an epoch lasts, bounding how many epochs a reward report can catch up on.
+    pub epoch_duration: RegisterView<TimeDelta>,
+    /// The last epoch rewards were reported for.
+    pub last_reward_epoch: RegisterView<Option<u64>>,
+    /// When the last accepted reward report was made.
+    pub last_reward_at: RegisterView<Option<Timestamp>>,
+    /// Every accepted reward report, oldest first.
+    pub reward_reports: LogView<RewardReport>,
+    /// Actions paused for every LST.
+    pub paused: RegisterView<PauseFlags>,
+    /// Actions paused for a single LST, on top of `paused`.
+    pub paused_lsts: MapView<ApplicationId, PauseFlags>,
+}
+
+/// Native tokens owed to a user for unstaked protocol LST.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct WithdrawalRequest {
+    pub id: u64,
+    /// The chain the native tokens are paid out to.
+    pub chain_id: ChainId,
+    pub amount: Amount,
+    pub claimable_at: Timestamp,
+}
+
+/// Staking rewards reported by the oracle for one epoch.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct RewardReport {
+    pub epoch: u64,
+    pub amount: Amount,
+    /// Pooled native tokens after the rewards were added.
+    pub total_pooled_native: Amount,
+    pub total_shares: Amount,
+    pub reported_at: Timestamp,
+}
+
+#[ComplexObject]
+impl LstState {
+    /// Native tokens one protocol LST is currently worth.
+    async fn protocol_lst_price(&self) -> Amount {
+        self.native_for_shares(Amount::ONE)
+    }
+
+    /// How much more of `token_id` the vault accepts, or `None` if it is uncapped.
+    async fn remaining_deposit_capacity(&self, token_id: ApplicationId) -> Option<Amount> {
+        let cap = self.lst_config(&token_id).await?.deposit_cap?;
+        Some(cap.saturating_sub(self.lst_deposit(&token_id).await))
+    }
+
+    /// How much more of `token_id` `owner` can deposit, or `None` if it is unlimited.
+    async fn remaining_user_deposit_capacity(&self, token_id: ApplicationId, owner: AccountOwner) -> Option<Amount> {
+        let config = self.lst_config(&token_id).await?;
+        let user_remaining = config.user_deposit_limit.map(|limit| limit.saturating_sub(self.user_deposit(&owner, &token_id).await));
+        let cap_remaining = config.deposit_cap.map(|cap| cap.saturating_sub(self.lst_deposit(&token_id).await));
+        match (user_remaining, cap_remaining) {
+            (Some(user), Some(cap)) => Some(user.min(cap)),
+            (user, cap) => user.or(cap),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl LstState {
+    /// Returns the configuration of `token_id`, if it is listed.
+    pub(crate) async fn lst_config(&self, token_id: &ApplicationId) -> Option<LstConfig> {
+        self.lst_configs.get(token_id).await.expect("Failed to get lst config")
+    }
+
+    /// Returns the amount of `token_id` the vault can pay out.
+    pub(crate) async fn reserve(&self, token_id: &ApplicationId) -> Amount {
+        self.reserves.get(token_id).await.expect("Failed to get reserves").unwrap_or_default()
+    }
+
+    /// Records `amount` of `token_id` coming into the vault reserves.
+    pub(crate) async fn add_reserves(&mut self, token_id: ApplicationId, amount: Amount) {
+        let reserve = self.reserve(&token_id).await.saturating_add(amount);
+        self.reserves.insert(&token_id, reserve).expect("Failed to insert reserves");
+    }
+
+    /// Records `amount` of `token_id` leaving the vault reserves, which must cover it.
+    pub(crate) async fn remove_reserves(&mut self, token_id: ApplicationId, amount: Amount) {
+        let mut reserve = self.reserve(&token_id).await;
+        reserve.try_sub_assign(amount).expect("Reserves underflow");
+        self.reserves.insert(&token_id, reserve).expect("Failed to insert reserves");
+    }
+
+    /// Credits `fee` of `token_id` to the treasury.
+    pub(crate) async fn add_fee(&mut self, token_id: ApplicationId, fee: Amount) {
+        if fee == Amount::ZERO {
+            return;
+        }
+        let mut balance = self.treasury.get(&token_id).await.expect("Failed to get treasury balance").unwrap_or_default();
+        balance.saturating_add_assign(fee);
+        self.treasury.insert(&token_id, balance).expect("Failed to insert treasury balance");
+    }
+
+    /// Debits `amount` of `token_id` from the treasury.
+    pub(crate) async fn take_fees(&mut self, token_id: ApplicationId, amount: Amount) -> Result<(), LstError> {
+        let mut balance = self.treasury.get(&token_id).await.expect("Failed to get treasury balance").unwrap_or_default();
+        balance.try_sub_assign(amount).map_err(|_| LstError::InsufficientFees(token_id))?;
+        if balance == Amount::ZERO {
+            self.treasury.remove(&token_id).expect("Failed to remove treasury balance");
+        } else {
+            self.treasury.insert(&token_id, balance).expect("Failed to insert treasury balance");
+        }
+        Ok(())
+    }
+
+    /// Returns whether the vault holds or owes any of `token_id`.
+    pub(crate) async fn has_funds(&self, token_id: &ApplicationId) -> bool {
+        let fees = self.treasury.get(token_id).await.expect("Failed to get treasury balance").unwrap_or_default();
+        let deposits = self.lst_deposit(token_id).await.saturating_add(self.pool_backing(token_id).await);
+        self.reserve(token_id).await > Amount::ZERO || deposits > Amount::ZERO || fees > Amount::ZERO
+    }
+
+    /// Returns the amount of `token_id` backing the pool.
+    pub(crate) async fn pool_backing(&self, token_id: &ApplicationId) -> Amount {
+        self.pool_backing.get(token_id).await.expect("Failed to get pool backing").unwrap_or_default()
+    }
+
+    /// Records `amount` of `token_id` exchanged for protocol LST, backing the pool.
+    pub(crate) async fn add_pool_backing(&mut self, token_id: ApplicationId, amount: Amount) {
+        let backing = self.pool_backing(&token_id).await.saturating_add(amount);
+        self.pool_backing.insert(&token_id, backing).expect("Failed to insert pool backing");
+    }
+
+    /// Records `amount` of `token_id` paid out of the vault, no longer backing the pool, and
+    /// returns how much of it did.
+    pub(crate) async fn remove_pool_backing(&mut self, token_id: ApplicationId, amount: Amount) -> Amount {
+        let old_backing = self.pool_backing(&token_id).await;
+        let backing = old_backing.saturating_sub(amount);
+        if backing == Amount::ZERO {
+            self.pool_backing.remove(&token_id).expect("Failed to remove pool backing");
+        } else {
+            self.pool_backing.insert(&token_id, backing).expect("Failed to insert pool backing");
+        }
+        old_backing.saturating_sub(backing)
+    }
+
+    /// Returns the amount of `token_id` users have deposited into the vault.
+    pub(crate) async fn lst_deposit(&self, token_id: &ApplicationId) -> Amount {
+        self.lst_deposits.get(token_id).await.expect("Failed to get lst deposit").unwrap_or_default()
+    }
+
+    /// Returns the amount of `token_id` `owner` has deposited into the vault.
+    pub(crate) async fn user_deposit(&self, owner: &AccountOwner, token_id: &ApplicationId) -> Amount {
+        let deposits = self.user_deposits.get(owner).await.expect("Failed to get user deposits").unwrap_or_default();
+        deposits.get(token_id).copied().unwrap_or_default()
+    }
+
+    /// Checks that depositing `amount` of `token_id` for `owner` stays within the limits of `config`.
+    pub(crate) async fn check_deposit(&self, config: &LstConfig, token_id: &ApplicationId, owner: &AccountOwner, amount: Amount) -> Result<(), LstError> {
+        if let Some(cap) = config.deposit_cap {
+            if self.lst_deposit(token_id).await.saturating_add(amount) > cap {
+                return Err(LstError::DepositCapExceeded(*token_id));
+            }
+        }
+        if let Some(limit) = config.user_deposit_limit {
+            if self.user_deposit(owner, token_id).await.saturating_add(amount) > limit {
+                return Err(LstError::UserDepositLimitExceeded(*token_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Records `amount` of `token_id` deposited into the vault by `owner`.
+    pub(crate) async fn record_deposit(&mut self, token_id: ApplicationId, owner: AccountOwner, amount: Amount) {
+        let total = self.lst_deposit(&token_id).await.saturating_add(amount);
+        self.lst_deposits.insert(&token_id, total).expect("Failed to insert lst deposit");
+
+        let mut deposits = self.user_deposits.get(&owner).await.expect("Failed to get user deposits").unwrap_or_default();
+        deposits.entry(token_id).or_default().saturating_add_assign(amount);
+        self.user_deposits.insert(&owner, deposits).expect("Failed to insert user deposits");
+    }
+
+    /// Records `amount` of `token_id` paid out of the vault to `owner`.
+    ///
+    /// Payouts can exceed what was deposited, e.g. LSTs the admin funded the vault with, so the
+    /// outstanding amounts only go down to zero.
+    pub(crate) async fn record_payout(&mut self, token_id: ApplicationId, owner: AccountOwner, amount: Amount) {
+        let total = self.lst_deposit(&token_id).await.saturating_sub(amount);
+        if total == Amount::ZERO {
+            self.lst_deposits.remove(&token_id).expect("Failed to remove lst deposit");
+        } else {
+            self.lst_deposits.insert(&token_id, total).expect("Failed to insert lst deposit");
+        }
+
+        let mut deposits = self.user_deposits.get(&owner).await.expect("Failed to get user deposits").unwrap_or_default();
+        if let Some(deposit) = deposits.get_mut(&token_id) {
+            *deposit = deposit.saturating_sub(amount);
+            if *deposit == Amount::ZERO {
+                deposits.remove(&token_id);
+            }
+            if deposits.is_empty() {
+                self.user_deposits.remove(&owner).expect("Failed to remove user deposits");
+            } else {
+                self.user_deposits.insert(&owner, deposits).expect("Failed to insert user deposits");
+            }
+        }
+    }
+
+    /// Returns whether `owner` approved `operator` to submit requests on their behalf.
+    pub(crate) async fn is_operator(&self, owner: &AccountOwner, operator: &AccountOwner) -> bool {
+        let operators = self.operators.get(owner).await.expect("Failed to get operators").unwrap_or_default();
+        operators.contains(operator)
+    }
+
+    /// Approves or revokes `operator` for `owner`.
+    pub(crate) async fn set_operator(&mut self, owner: AccountOwner, operator: AccountOwner, approved: bool) {
+        let mut operators = self.operators.get(&owner).await.expect("Failed to get operators").unwrap_or_default();
+        if approved {
+            operators.insert(operator);
+        } else {
+            operators.remove(&operator);
+        }
+        if operators.is_empty() {
+            self.operators.remove(&owner).expect("Failed to remove operators");
+        } else {
+            self.operators.insert(&owner, operators).expect("Failed to insert operators");
+        }
+    }
+
+    /// Returns the number of native tokens one unit of `token_id` is worth.
+    pub(crate) async fn exchange_rate(&self, token_id: &ApplicationId) -> Amount {
+        self.exchange_rates.get(token_id).await.expect("Failed to get exchange rate").unwrap_or(Amount::ONE)
+    }
+
+    /// Returns the protocol LST shares `native` buys at the current pool price.
+    pub(crate) fn shares_for_native(&self, native: Amount) -> Amount {
+        let pooled = *self.total_pooled_native.get();
+        let shares = *self.total_shares.get();
+        if pooled == Amount::ZERO || shares == Amount::ZERO {
+            return native;
+        }
+        mul_div(native, shares, pooled)
+    }
+
+    /// Returns the native tokens `shares` of the protocol LST are worth at the current pool price.
+    pub(crate) fn native_for_shares(&self, shares: Amount) -> Amount {
+        let total_shares = *self.total_shares.get();
+        if total_shares == Amount::ZERO {
+            return shares;
+        }
+        mul_div(shares, *self.total_pooled_native.get(), total_shares)
+    }
+
+    /// Queues a withdrawal of `amount` native tokens for `owner`, returning the request id.
+    pub(crate) async fn add_withdrawal_request(&mut self, owner: AccountOwner, chain_id: ChainId, amount: Amount, now: Timestamp) -> u64 {
+        let id = *self.next_withdrawal_id.get();
+        self.next_withdrawal_id.set(id + 1);
+
+        let claimable_at = now.saturating_add(*self.withdrawal_cooldown.get());
+        let request = WithdrawalRequest { id, chain_id, amount, claimable_at };
+        let mut requests = self.withdrawal_requests.get(&owner).await.expect("Failed to get withdrawal requests").unwrap_or_default();
+        requests.push(request);
+        self.withdrawal_requests.insert(&owner, requests).expect("Failed to insert withdrawal requests");
+        id
+    }
+
+    /// Removes and returns the withdrawal request `id` of `owner` if it can be claimed at `now`.
+    pub(crate) async fn take_matured_withdrawal(&mut self, owner: AccountOwner, id: u64, now: Timestamp) -> Result<WithdrawalRequest, LstError> {
+        let mut requests = self.withdrawal_requests.get(&owner).await.expect("Failed to get withdrawal requests").unwrap_or_default();
+        let index = requests.iter().position(|request| request.id == id).ok_or(LstError::WithdrawalNotFound(id))?;
+        if requests[index].claimable_at > now {
+            return Err(LstError::WithdrawalNotMatured(id));
+        }
+
+        let request = requests.remove(index);
+        if requests.is_empty() {
+            self.withdrawal_requests.remove(&owner).expect("Failed to remove withdrawal requests");
+        } else {
+            self.withdrawal_requests.insert(&owner, requests).expect("Failed to insert withdrawal requests");
+        }
+        Ok(request)
+    }
+
+    /// Checks that `action` is paused neither globally nor for any of `token_ids`.
+    pub(crate) async fn check_not_paused(&self, action: LstAction, token_ids: &[ApplicationId]) -> Result<(), LstError> {
+        if self.paused.get().is_paused(action) {
+            return Err(LstError::Paused(action));
+        }
+        for token_id in token_ids {
+            let flags = self.paused_lsts.get(token_id).await.expect("Failed to get pause flags").unwrap_or_default();
+            if flags.is_paused(action) {
+                return Err(LstError::Paused(action));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the pause flags, globally when `token_id` is `None`.
+    pub(crate) async fn pause_flags(&self, token_id: Option<ApplicationId>) -> PauseFlags {
+        match token_id {
+            None => *self.paused.get(),
+            Some(token_id) => self.paused_lsts.get(&token_id).await.expect("Failed to get pause flags").unwrap_or_default(),
+        }
+    }
+
+    /// Replaces the pause flags, globally when `token_id` is `None`.
+    pub(crate) fn set_paused(&mut self, token_id: Option<ApplicationId>, flags: PauseFlags) {
+        match token_id {
+            None => self.paused.set(flags),
+            Some(token_id) if flags == PauseFlags::default() => self.paused_lsts.remove(&token_id).expect("Failed to remove pause flags"),
+            Some(token_id) => self.paused_lsts.insert(&token_id, flags).expect("Failed to insert pause flags"),
+        }
+    }
+
+    /// Adds the rewards `amount` of `epoch` to the pool if they are within the per-epoch bound.
+    ///
+    /// A report after a gap may cover several epochs, but only as many as the time since the last
+    /// report allows, so skipping epoch numbers doesn't raise the bound.
+    pub(crate) fn report_rewards(&mut self, amount: Amount, epoch: u64, now: Timestamp) -> Result<RewardReport, LstError> {
+        let skipped_epochs = match *self.last_reward_epoch.get() {
+            Some(last_epoch) if epoch <= last_epoch => return Err(LstError::StaleEpoch(epoch)),
+            Some(last_epoch) => epoch - last_epoch,
+            None => 1,
+        };
+        let epoch_duration = self.epoch_duration.get().as_micros();
+        let elapsed_epochs = match *self.last_reward_at.get() {
+            Some(last_at) if epoch_duration > 0 => (now.delta_since(last_at).as_micros() / epoch_duration).clamp(1, skipped_epochs),
+            _ => 1,
+        };
+        let pooled = *self.total_pooled_native.get();
+        let max_bps = u128::from(*self.max_reward_bps.get()).saturating_mul(elapsed_epochs.into());
+        let bound = mul_div(pooled, Amount::from_attos(max_bps), Amount::from_attos(10_000));
+        if amount > bound {
+            return Err(LstError::RewardTooLarge);
+        }
+
+        self.add_to_pool(amount, Amount::ZERO);
+        self.last_reward_epoch.set(Some(epoch));
+        self.last_reward_at.set(Some(now));
+        let report = RewardReport {
+            epoch,
+            amount,
+            total_pooled_native: *self.total_pooled_native.get(),
+            total_shares: *self.total_shares.get(),
+            reported_at: now,
+        };
+        self.reward_reports.push(report.clone());
+        Ok(report)
+    }
+
+    /// Removes `native` lost by the pool without retiring any shares, lowering the protocol LST price.
+    pub(crate) fn socialize_loss(&mut self, native: Amount) -> Amount {
+        let pooled = *self.total_pooled_native.get();
+        let loss = native.min(pooled);
+        self.total_pooled_native.set(pooled.saturating_sub(loss));
+        loss
+    }
+
+    /// Sets aside `native` of the vault's native liquidity for a redemption.
+    ///
+    /// The liquidity buffer is shrunk to what is left, so that it can always be paid out.
+    pub(crate) fn reserve_native(&mut self, native: Amount) -> Result<(), LstError> {
+        self.native_liquidity.get_mut().try_sub_assign(native).map_err(|_| LstError::InsufficientNativeLiquidity)?;
+        let buffer = (*self.liquidity_buffer.get()).min(*self.native_liquidity.get());
+        self.liquidity_buffer.set(buffer);
+        Ok(())
+    }
+
+    /// Returns the fee for paying out `native` from the liquidity buffer right away, or `None` if
+    /// the buffer can't cover it.
+    pub(crate) fn instant_unstake_fee(&self, native: Amount) -> Option<Amount> {
+        let config = self.instant_unstake.get();
+        let buffer = *self.liquidity_buffer.get();
+        if config.buffer_target == Amount::ZERO {
+            return None;
+        }
+        // the more of the buffer the exit uses up, the higher the fee
+        let remaining = buffer.saturating_sub(native).min(config.buffer_target);
+        let remaining_bps = u128::from(mul_div(remaining, Amount::from_attos(10_000), config.buffer_target));
+        let used_bps = 10_000 - remaining_bps;
+        let fee_range = u128::from(config.max_fee_bps - config.min_fee_bps);
+        let fee_bps = u128::from(config.min_fee_bps) + fee_range * used_bps / 10_000;
+        let fee = fee_for(native, u16::try_from(fee_bps).expect("Fee is at most max_fee_bps"));
+        (native.saturating_sub(fee) <= buffer).then_some(fee)
+    }
+
+    /// Adds freshly staked native tokens to the liquidity buffer, up to its target.
+    pub(crate) fn refill_buffer(&mut self, native: Amount) {
+        let target = self.instant_unstake.get().buffer_target;
+        let buffer = self.liquidity_buffer.get().saturating_add(native).min(target);
+        self.liquidity_buffer.set(buffer);
+    }
+
+    /// Returns a fresh id for a request sent from this chain.
+    pub(crate) fn next_request_id(&mut self) -> u64 {
+        let id = *self.next_request_id.get();
+        self.next_request_id.set(id + 1);
+        id
+    }
+
+    /// Adds `native` to the pool and issues `shares` against it.
+    pub(crate) fn add_to_pool(&mut self, native: Amount, shares: Amount) {
+        self.total_pooled_native.get_mut().saturating_add_assign(native);
+        self.total_shares.get_mut().saturating_add_assign(shares);
+    }
+
+    /// Removes `native` from the pool and retires `shares` redeemed for it.
+    pub(crate) fn remove_from_pool(&mut self, native: Amount, shares: Amount) {
+        self.total_pooled_native.get_mut().try_sub_assign(native).expect("Pooled native underflow");
+        self.total_shares.get_mut().try_sub_assign(shares).expect("Protocol lst shares underflow");
+    }
 }
@@ -1,10 +1,11 @@
 #![cfg(not(target_arch = "wasm32"))]
 
+use async_graphql::InputType;
 use linera_sdk::{
-    linera_base_types::{Account, AccountOwner, Amount, ChainId},
-    test::{Recipient, TestValidator},
+    linera_base_types::{Account, AccountOwner, Amount, ApplicationId, ChainId, TimeDelta},
+    test::{ActiveChain, Medium, MessageAction, QueryOutcome, Recipient, TestValidator},
 };
-use lst::{LstAbi, Operation, Parameters};
+use lst::{request_memo, InstantUnstakeConfig, InstantiationArgument, LstAbi, LstConfig, Operation, Parameters, PauseFlags};
 
 /////////// Add new lst token to lst app ///////////
 /// 1. create protocol liquid token
@@ -37,10 +38,20 @@ async fn add_new_lst() {
     assert_eq!(admin_balance, Some(Amount::from_tokens(100)));
 
     // create lst app
-    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, ()>().await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
 
     let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
-    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, (), vec![]).await;
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
+        .await;
 
     // create new stake token "FOO"
     let foo_token_module_id = stake_chain
@@ -58,19 +69,14 @@ async fn add_new_lst() {
     assert_eq!(admin_foo_balance, Some(foo_initial_amount));
 
     //transfer all "FOO" lst to stake chain
-    let lst_app_vault = fungible::Account {
-        chain_id: stake_chain.id(),
-        owner: lst_id.application_description_hash.into(),
-    };
-
     stake_chain
         .add_block(|block| {
             block.with_operation(
-                foo_token_id,
-                fungible::Operation::Transfer {
+                lst_id,
+                Operation::ProvideLiquidity {
                     owner: admin_account,
+                    token_id: foo_token_id.forget_abi(),
                     amount: foo_initial_amount,
-                    target_account: lst_app_vault,
                 },
             );
         })
@@ -82,7 +88,13 @@ async fn add_new_lst() {
 
     stake_chain
         .add_block(|block| {
-            block.with_operation(lst_id, Operation::NewLst { token_id: foo_token_id.forget_abi() });
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
         })
         .await;
 }
@@ -91,7 +103,7 @@ async fn add_new_lst() {
 /// 1. create protocol liquid token
 /// 2. create user chain and fund it with native tokens
 /// 3. create lst app with liquid token as parameter
-/// 4. transfer protocol lst to lst app vault
+/// 4. make the lst app the minter of the protocol lst
 /// 5. user stakes native tokens to get protocol lst
 /// 6. verify user received protocol lst and app received native tokens
 #[test_log::test(tokio::test)]
@@ -134,7 +146,10 @@ async fn native_stake_protocol_lst() {
 
     // create protocol lst
     let initial_token_state = fungible::InitialStateBuilder::default().with_account(admin_account, Amount::from_tokens(100));
-    let params_a = fungible::Parameters::new("PLST");
+    let params_a = fungible::Parameters {
+        mintable: true,
+        ..fungible::Parameters::new("PLST")
+    };
     let protocol_lst_id = stake_chain.create_application(protocol_token_module_id, params_a, initial_token_state.build(), vec![]).await;
 
     // check if admin has protocol lst
@@ -142,30 +157,24 @@ async fn native_stake_protocol_lst() {
     assert_eq!(admin_balance, Some(Amount::from_tokens(100)));
 
     // create lst app
-    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, ()>().await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
 
     let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
-    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, (), vec![]).await;
-
-    //transfer all lst to stake chain
-    let lst_app_vault = fungible::Account {
-        chain_id: stake_chain.id(),
-        owner: lst_id.application_description_hash.into(),
-    };
-
-    stake_chain
-        .add_block(|block| {
-            block.with_operation(
-                protocol_lst_id,
-                fungible::Operation::Transfer {
-                    owner: admin_account,
-                    amount: Amount::from_tokens(100),
-                    target_account: lst_app_vault,
-                },
-            );
-        })
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
         .await;
 
+    // let the lst app mint protocol lst
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
     // stake native token and get lst by user on user chain
     let stake_cert = user_chain
         .add_block(|block| {
@@ -175,6 +184,7 @@ async fn native_stake_protocol_lst() {
                     user: user_account,
                     amount: Amount::from_tokens(10),
                     lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
                 },
             );
         })
@@ -198,7 +208,7 @@ async fn native_stake_protocol_lst() {
 /// 1. create user chain and fund it with native tokens
 /// 2. create protocol liquid token
 /// 3. create lst app with liquid token as parameter
-/// 4. transfer protocol lst to lst app vault
+/// 4. make the lst app the minter of the protocol lst
 /// 5. create new lst token "FOO"
 /// 6. transfer all "FOO" lst to stake chain
 /// 7. use Operation::NewLst to add new lst
@@ -243,7 +253,10 @@ async fn native_stake_other_lst_type() {
 
     // create protocol lst
     let initial_token_state = fungible::InitialStateBuilder::default().with_account(admin_account, Amount::from_tokens(100));
-    let params_a = fungible::Parameters::new("PLST");
+    let params_a = fungible::Parameters {
+        mintable: true,
+        ..fungible::Parameters::new("PLST")
+    };
     let protocol_lst_id = stake_chain.create_application(protocol_token_module_id, params_a, initial_token_state.build(), vec![]).await;
 
     // check if admin has protocol lst
@@ -251,30 +264,24 @@ async fn native_stake_other_lst_type() {
     assert_eq!(admin_balance, Some(Amount::from_tokens(100)));
 
     // create lst app
-    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, ()>().await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
 
     let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
-    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, (), vec![]).await;
-
-    //transfer all lst to stake chain
-    let lst_app_vault = fungible::Account {
-        chain_id: stake_chain.id(),
-        owner: lst_id.application_description_hash.into(),
-    };
-
-    stake_chain
-        .add_block(|block| {
-            block.with_operation(
-                protocol_lst_id,
-                fungible::Operation::Transfer {
-                    owner: admin_account,
-                    amount: Amount::from_tokens(100),
-                    target_account: lst_app_vault,
-                },
-            );
-        })
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
         .await;
 
+    // let the lst app mint protocol lst
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
     // create new stake token "FOO"
     let foo_token_module_id = stake_chain
         .publish_bytecode_files_in::<fungible::FungibleTokenAbi, fungible::Parameters, fungible::InitialState>("../fungible")
@@ -294,11 +301,11 @@ async fn native_stake_other_lst_type() {
     stake_chain
         .add_block(|block| {
             block.with_operation(
-                foo_token_id,
-                fungible::Operation::Transfer {
+                lst_id,
+                Operation::ProvideLiquidity {
                     owner: admin_account,
+                    token_id: foo_token_id.forget_abi(),
                     amount: foo_initial_amount,
-                    target_account: lst_app_vault,
                 },
             );
         })
@@ -311,7 +318,13 @@ async fn native_stake_other_lst_type() {
     // add new lst
     let new_lst_cert = stake_chain
         .add_block(|block| {
-            block.with_operation(lst_id, Operation::NewLst { token_id: foo_token_id.forget_abi() });
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
         })
         .await;
 
@@ -331,6 +344,7 @@ async fn native_stake_other_lst_type() {
                     user: user_account,
                     amount: Amount::from_tokens(10),
                     lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
                 },
             );
         })
@@ -356,7 +370,7 @@ async fn native_stake_other_lst_type() {
 /// 1. create user chain and fund it with native tokens
 /// 2. create protocol liquid token
 /// 3. create lst app with liquid token as parameter
-/// 4. transfer protocol lst to lst app vault
+/// 4. make the lst app the minter of the protocol lst
 /// 5. create new lst token "FOO"
 /// 6. transfer all "FOO" lst to stake chain
 /// 7. use Operation::NewLst to add new lst
@@ -402,7 +416,10 @@ async fn stake_lst() {
 
     // create protocol lst
     let initial_token_state = fungible::InitialStateBuilder::default().with_account(admin_account, Amount::from_tokens(100));
-    let params_a = fungible::Parameters::new("PLST");
+    let params_a = fungible::Parameters {
+        mintable: true,
+        ..fungible::Parameters::new("PLST")
+    };
     let protocol_lst_id = stake_chain.create_application(protocol_token_module_id, params_a, initial_token_state.build(), vec![]).await;
 
     // check if admin has protocol lst
@@ -410,30 +427,24 @@ async fn stake_lst() {
     assert_eq!(admin_balance, Some(Amount::from_tokens(100)));
 
     // create lst app
-    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, ()>().await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
 
     let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
-    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, (), vec![]).await;
-
-    //transfer all lst to stake chain
-    let lst_app_vault = fungible::Account {
-        chain_id: stake_chain.id(),
-        owner: lst_id.application_description_hash.into(),
-    };
-
-    stake_chain
-        .add_block(|block| {
-            block.with_operation(
-                protocol_lst_id,
-                fungible::Operation::Transfer {
-                    owner: admin_account,
-                    amount: Amount::from_tokens(100),
-                    target_account: lst_app_vault,
-                },
-            );
-        })
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
         .await;
 
+    // let the lst app mint protocol lst
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
     // create new stake token "FOO"
     let foo_token_module_id = stake_chain
         .publish_bytecode_files_in::<fungible::FungibleTokenAbi, fungible::Parameters, fungible::InitialState>("../fungible")
@@ -453,11 +464,11 @@ async fn stake_lst() {
     stake_chain
         .add_block(|block| {
             block.with_operation(
-                foo_token_id,
-                fungible::Operation::Transfer {
+                lst_id,
+                Operation::ProvideLiquidity {
                     owner: admin_account,
+                    token_id: foo_token_id.forget_abi(),
                     amount: foo_initial_amount,
-                    target_account: lst_app_vault,
                 },
             );
         })
@@ -470,7 +481,13 @@ async fn stake_lst() {
     // add new lst
     let new_lst_cert = stake_chain
         .add_block(|block| {
-            block.with_operation(lst_id, Operation::NewLst { token_id: foo_token_id.forget_abi() });
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
         })
         .await;
 
@@ -490,6 +507,7 @@ async fn stake_lst() {
                     user: user_account,
                     amount: Amount::from_tokens(10),
                     lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
                 },
             );
         })
@@ -567,6 +585,15 @@ async fn stake_lst() {
     let user_balance = fungible::query_account(protocol_lst_id, &stake_chain, user_account).await;
     assert_eq!(user_balance, None);
 
+    // the "FOO" lst came into the vault tagged with the stake request, the second one of the user chain
+    let query = format!("query {{ history(owner: {}, limit: 1) {{ direction amount memo }} }}", AccountOwner::from(lst_id).to_value());
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(foo_token_id, query).await;
+    let transfer = &response["history"][0];
+    assert_eq!(transfer["direction"], "INCOMING");
+    assert_eq!(transfer["amount"], "10.");
+    let memo = transfer["memo"].as_array().expect("Missing memo").iter().map(|byte| byte.as_u64().unwrap() as u8).collect::<Vec<_>>();
+    assert_eq!(memo, request_memo(1));
+
     // check app native balance
     let app_native_balance = stake_chain.owner_balance(&lst_id.application_description_hash.into()).await;
     assert_eq!(app_native_balance, Some(Amount::from_tokens(10)));
@@ -576,7 +603,7 @@ async fn stake_lst() {
 /// 1. create user chain and fund it with native tokens
 /// 2. create protocol liquid token
 /// 3. create lst app with liquid token as parameter
-/// 4. transfer protocol lst to lst app vault
+/// 4. make the lst app the minter of the protocol lst
 /// 5. create new lst token "FOO"
 /// 6. transfer all "FOO" lst to stake chain
 /// 7. create new lst token "BAR"
@@ -629,7 +656,10 @@ async fn swap() {
     // create protocol lst
     let initial_plst_amount = Amount::from_tokens(1000);
     let initial_token_state = fungible::InitialStateBuilder::default().with_account(admin_account, initial_plst_amount);
-    let params_a = fungible::Parameters::new("PLST");
+    let params_a = fungible::Parameters {
+        mintable: true,
+        ..fungible::Parameters::new("PLST")
+    };
     let protocol_lst_id = stake_chain.create_application(protocol_token_module_id, params_a, initial_token_state.build(), vec![]).await;
 
     // check if admin has protocol lst
@@ -637,34 +667,29 @@ async fn swap() {
     assert_eq!(admin_balance, Some(initial_plst_amount));
 
     // create lst app
-    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, ()>().await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
 
     let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
-    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, (), vec![]).await;
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
+        .await;
 
     let lst_app_vault_account: AccountOwner = lst_id.application_description_hash.into();
 
-    //transfer all lst to stake chain
-    let lst_app_vault = fungible::Account {
-        chain_id: stake_chain.id(),
-        owner: lst_app_vault_account,
-    };
-
-    stake_chain
-        .add_block(|block| {
-            block.with_operation(
-                protocol_lst_id,
-                fungible::Operation::Transfer {
-                    owner: admin_account,
-                    amount: initial_plst_amount,
-                    target_account: lst_app_vault,
-                },
-            );
-        })
-        .await;
+    // let the lst app mint protocol lst
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
 
+    // the vault holds no protocol lst, it is minted on stake
     let lst_app_vault_balance = fungible::query_account(protocol_lst_id, &stake_chain, lst_app_vault_account).await;
-    assert_eq!(lst_app_vault_balance, Some(initial_plst_amount));
+    assert_eq!(lst_app_vault_balance, None);
 
     // create new stake token "FOO"
     let foo_token_module_id = stake_chain
@@ -685,11 +710,11 @@ async fn swap() {
     stake_chain
         .add_block(|block| {
             block.with_operation(
-                foo_token_id,
-                fungible::Operation::Transfer {
+                lst_id,
+                Operation::ProvideLiquidity {
                     owner: admin_account,
+                    token_id: foo_token_id.forget_abi(),
                     amount: foo_initial_amount,
-                    target_account: lst_app_vault,
                 },
             );
         })
@@ -717,11 +742,11 @@ async fn swap() {
     stake_chain
         .add_block(|block| {
             block.with_operation(
-                bar_token_id,
-                fungible::Operation::Transfer {
+                lst_id,
+                Operation::ProvideLiquidity {
                     owner: admin_account,
+                    token_id: bar_token_id.forget_abi(),
                     amount: bar_initial_amount,
-                    target_account: lst_app_vault,
                 },
             );
         })
@@ -733,14 +758,26 @@ async fn swap() {
     // add new FOO lst
     stake_chain
         .add_block(|block| {
-            block.with_operation(lst_id, Operation::NewLst { token_id: foo_token_id.forget_abi() });
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
         })
         .await;
 
     // add new BAR lst
     stake_chain
         .add_block(|block| {
-            block.with_operation(lst_id, Operation::NewLst { token_id: bar_token_id.forget_abi() });
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: bar_token_id.forget_abi(),
+                    config: LstConfig::new("BAR"),
+                },
+            );
         })
         .await;
 
@@ -753,6 +790,7 @@ async fn swap() {
                     user: user_account,
                     amount: Amount::from_tokens(100),
                     lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
                 },
             );
         })
@@ -767,6 +805,7 @@ async fn swap() {
                     user: user_account,
                     amount: Amount::from_tokens(100),
                     lst_type_out: bar_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
                 },
             );
         })
@@ -852,10 +891,10 @@ async fn swap() {
 
     // App should have:
     // - 920 FOO (1000 initial - 100 + 20)
-    // - 980 PLST (1000 initial - 20 staked for protocol)
+    // - no PLST (20 minted to the user)
     assert_eq!(app_foo_balance, Some(Amount::from_tokens(920)));
     assert_eq!(app_bar_balance, Some(Amount::from_tokens(900)));
-    assert_eq!(app_protocol_balance, Some(Amount::from_tokens(980)));
+    assert_eq!(app_protocol_balance, None);
 
     // swap "BAR" for "FOO"
     let swap_cert = user_chain
@@ -867,6 +906,7 @@ async fn swap() {
                     amount_in: Amount::from_tokens(10),
                     lst_type_in: bar_token_id.forget_abi(),
                     lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
                 },
             );
         })
@@ -907,8 +947,1820 @@ async fn swap() {
     // App should have:
     // - 910 FOO (1000 initial - 100 + 20 - 10)
     // - 910 BAR (1000 initial - 100 + 10)
-    // - 980 PLST (1000 initial - 20 staked for protocol)
+    // - no PLST (20 minted to the user)
     assert_eq!(app_foo_balance, Some(Amount::from_tokens(910)));
     assert_eq!(app_bar_balance, Some(Amount::from_tokens(910)));
-    assert_eq!(app_protocol_balance, Some(Amount::from_tokens(980)));
+    assert_eq!(app_protocol_balance, None);
+}
+
+/////////// Native stake with exchange rate scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. create new lst token "FOO" and transfer it to the lst app vault
+/// 4. use Operation::NewLst to add new lst
+/// 5. set "FOO" exchange rate to 2 native per "FOO"
+/// 6. stake native to get "FOO" lst and check only half as many "FOO" are received
+#[test_log::test(tokio::test)]
+async fn native_stake_with_exchange_rate() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
+        .await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    fund_vault(&stake_chain, lst_id, foo_token_id, admin_account, Amount::from_tokens(100)).await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::UpdateExchangeRate {
+                    token_id: foo_token_id.forget_abi(),
+                    rate: Amount::from_tokens(2),
+                },
+            );
+        })
+        .await;
+
+    // stake 10 native at 2 native per "FOO"
+    let stake_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+
+    let stake_msg = stake_chain
+        .add_block(|block| {
+            block.with_messages_from(&stake_cert);
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_messages_from(&stake_msg);
+        })
+        .await;
+
+    let user_balance = fungible::query_account(foo_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(5)));
+
+    let app_foo_balance = fungible::query_account(foo_token_id, &stake_chain, AccountOwner::from(lst_id)).await;
+    assert_eq!(app_foo_balance, Some(Amount::from_tokens(95)));
+
+    let app_native_balance = stake_chain.owner_balance(&lst_id.application_description_hash.into()).await;
+    assert_eq!(app_native_balance, Some(Amount::from_tokens(10)));
+}
+
+/////////// Stake fee scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. create new lst token "FOO", transfer it to the lst app vault and add it with a 10% stake fee
+/// 4. stake native for "FOO"
+/// 5. check the user receives "FOO" minus the fee
+/// 6. withdraw the fee to the admin and check balances
+#[test_log::test(tokio::test)]
+async fn native_stake_fee_is_withdrawn() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    fund_vault(&stake_chain, lst_id, foo_token_id, admin_account, Amount::from_tokens(100)).await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig {
+                        stake_fee_bps: 1_000,
+                        ..LstConfig::new("FOO")
+                    },
+                },
+            );
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(foo_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(9)));
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::WithdrawFees {
+                    token_id: foo_token_id.forget_abi(),
+                    amount: Amount::from_tokens(1),
+                    target_account: Account::new(stake_chain.id(), admin_account),
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+
+    let admin_balance = fungible::query_account(foo_token_id, &stake_chain, admin_account).await;
+    assert_eq!(admin_balance, Some(Amount::from_tokens(1)));
+    let app_foo_balance = fungible::query_account(foo_token_id, &stake_chain, AccountOwner::from(lst_id)).await;
+    assert_eq!(app_foo_balance, Some(Amount::from_tokens(90)));
+}
+
+/////////// Insufficient liquidity scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. create new lst token "FOO" and add it without providing liquidity
+/// 4. stake native for "FOO"
+/// 5. check the native tokens are refunded and no "FOO" is sent
+#[test_log::test(tokio::test)]
+async fn native_stake_without_liquidity_is_refunded() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(1000)));
+    let user_balance = fungible::query_account(foo_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, None);
+}
+
+/////////// Unstake protocol lst scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app, and make the app its minter
+/// 3. stake native to get protocol lst
+/// 4. unstake part of the protocol lst and check a withdrawal request is queued at the pool price
+/// 5. claim the withdrawal and check native tokens are returned
+#[test_log::test(tokio::test)]
+async fn unstake_protocol_lst() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
+        .await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    // stake native token and get protocol lst
+    let stake_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+
+    let stake_msg = stake_chain
+        .add_block(|block| {
+            block.with_messages_from(&stake_cert);
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_messages_from(&stake_msg);
+        })
+        .await;
+
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(10)));
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(990)));
+
+    // unstake 4 protocol lst
+    let unstake_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::Unstake {
+                    owner: user_account,
+                    amount: Amount::from_tokens(4),
+                },
+            );
+        })
+        .await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_messages_from(&unstake_cert);
+        })
+        .await;
+
+    // the native tokens are queued for withdrawal
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(6)));
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(990)));
+
+    let query = format!("query {{ withdrawalRequests {{ entry(key: {}) {{ value {{ id amount }} }} }} }}", user_account.to_value());
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, query).await;
+    let requests = response
+        .pointer("/withdrawalRequests/entry/value")
+        .and_then(|value| value.as_array())
+        .expect("Missing withdrawal requests");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["amount"].as_str().map(|amount| amount.parse::<Amount>().unwrap()), Some(Amount::from_tokens(4)));
+
+    // claim the withdrawal, the cooldown is zero
+    let claim_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(lst_id, Operation::ClaimWithdrawal { owner: user_account, request_id: 0 });
+        })
+        .await;
+
+    let claim_msg = stake_chain
+        .add_block(|block| {
+            block.with_messages_from(&claim_cert);
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_messages_from(&claim_msg);
+        })
+        .await;
+
+    // check user balances
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(994)));
+
+    // check app balances, the unstaked protocol lst was burned
+    let app_protocol_balance = fungible::query_account(protocol_lst_id, &stake_chain, AccountOwner::from(lst_id)).await;
+    assert_eq!(app_protocol_balance, None);
+    let app_native_balance = stake_chain.owner_balance(&lst_id.application_description_hash.into()).await;
+    assert_eq!(app_native_balance, Some(Amount::from_tokens(6)));
+}
+
+/////////// Large pool scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. stake 2000 native for protocol lst and report 100 native of rewards
+/// 4. stake 1050 native at the raised price and unstake 500 protocol lst
+/// 5. check the shares and the queued native tokens follow the exchange rate
+#[test_log::test(tokio::test)]
+async fn stake_and_unstake_large_pool() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(5000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(2000),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ConfigureOracle {
+                    oracle: admin_account,
+                    max_reward_bps: 1_000,
+                    epoch_duration: TimeDelta::from_secs(3600),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::ReportRewards {
+                    amount: Amount::from_tokens(100),
+                    epoch: 1,
+                },
+            );
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { protocolLstPrice }").await;
+    let price = response["protocolLstPrice"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(price, Some(Amount::from_millis(1_050)));
+
+    // 1050 native buy 1000 shares of the 2100 native pool
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(1050),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(3000)));
+
+    // 500 shares of the 3150 native pool redeem 525 native
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::Unstake {
+                    owner: user_account,
+                    amount: Amount::from_tokens(500),
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(2500)));
+
+    let query = format!("query {{ withdrawalRequests {{ entry(key: {}) {{ value {{ id amount }} }} }} }}", user_account.to_value());
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, query).await;
+    let requests = response
+        .pointer("/withdrawalRequests/entry/value")
+        .and_then(|value| value.as_array())
+        .expect("Missing withdrawal requests");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["amount"].as_str().map(|amount| amount.parse::<Amount>().unwrap()), Some(Amount::from_tokens(525)));
+}
+
+/////////// Only admin can add new lst scenario ///////////
+/// 1. create protocol liquid token
+/// 2. create lst app with another account as admin
+/// 3. check Operation::NewLst signed by the stake chain owner doesn't approve the lst
+#[test_log::test(tokio::test)]
+async fn new_lst_requires_admin() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let chain_owner = AccountOwner::from(stake_chain.public_key());
+    let admin_account = AccountOwner::from(validator.new_chain().await.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", chain_owner, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let lst_id = stake_chain
+        .create_application(
+            lst_module_id,
+            stake_parameter,
+            InstantiationArgument {
+                admin: admin_account,
+                withdrawal_cooldown: TimeDelta::from_secs(0),
+            },
+            vec![],
+        )
+        .await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", chain_owner, Amount::from_tokens(100)).await;
+
+    // the operation is answered with `LstError::NotAdmin` and changes nothing
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+        })
+        .await;
+
+    // only the protocol lst is approved
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { lstConfigs { keys } }").await;
+    assert_eq!(response["lstConfigs"]["keys"].as_array().map(Vec::len), Some(1));
+}
+
+/////////// Reward report scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. stake native for protocol lst
+/// 4. make the admin the oracle and report rewards above and within the per-epoch bound
+/// 5. check only the report within the bound raises the protocol lst price and is recorded
+/// 6. report rewards for a much later epoch right away
+/// 7. check they are still bounded by a single epoch
+#[test_log::test(tokio::test)]
+async fn report_rewards() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+
+    // at most 10% per epoch, so only the second report is accepted
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ConfigureOracle {
+                    oracle: admin_account,
+                    max_reward_bps: 1_000,
+                    epoch_duration: TimeDelta::from_secs(3600),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::ReportRewards {
+                    amount: Amount::from_tokens(2),
+                    epoch: 1,
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::ReportRewards {
+                    amount: Amount::from_tokens(1),
+                    epoch: 1,
+                },
+            );
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { protocolLstPrice rewardReports { entries { epoch amount } } }").await;
+    let price = response["protocolLstPrice"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(price, Some(Amount::from_millis(1_100)));
+    let reports = response["rewardReports"]["entries"].as_array().expect("reward reports");
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0]["epoch"].as_u64(), Some(1));
+
+    // skipping to epoch 6 within the same hour still only allows one epoch of rewards
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ReportRewards {
+                    amount: Amount::from_tokens(2),
+                    epoch: 6,
+                },
+            );
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { protocolLstPrice rewardReports { entries { epoch amount } } }").await;
+    let price = response["protocolLstPrice"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(price, Some(Amount::from_millis(1_100)));
+    assert_eq!(response["rewardReports"]["entries"].as_array().map(Vec::len), Some(1));
+}
+
+/////////// Loss report scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. stake native for protocol lst
+/// 4. make the admin the oracle and report a 10% loss of the pooled native tokens, and pause
+/// 5. check the protocol lst price drops for every holder and a total loss is rejected
+/// 6. check staking is paused globally
+#[test_log::test(tokio::test)]
+async fn report_pool_loss() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ConfigureOracle {
+                    oracle: admin_account,
+                    max_reward_bps: 0,
+                    epoch_duration: TimeDelta::from_secs(3600),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::ReportLoss {
+                    token_id: None,
+                    loss_bps: 1_000,
+                    pause: true,
+                },
+            );
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { protocolLstPrice totalPooledNative }").await;
+    let price = response["protocolLstPrice"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(price, Some(Amount::from_millis(900)));
+    let pooled = response["totalPooledNative"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(pooled, Some(Amount::from_tokens(9)));
+
+    // the operation is answered with `LstError::InvalidLoss`, a total loss would reset the price
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ReportLoss {
+                    token_id: None,
+                    loss_bps: 10_000,
+                    pause: false,
+                },
+            );
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { totalPooledNative }").await;
+    let pooled = response["totalPooledNative"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(pooled, Some(Amount::from_tokens(9)));
+
+    // the loss paused staking and swapping every lst
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { paused { stake swap unstake } }").await;
+    assert_eq!(response.pointer("/paused/stake").and_then(|paused| paused.as_bool()), Some(true));
+    assert_eq!(response.pointer("/paused/swap").and_then(|paused| paused.as_bool()), Some(true));
+    assert_eq!(response.pointer("/paused/unstake").and_then(|paused| paused.as_bool()), Some(false));
+}
+
+/////////// Lst loss scenario ///////////
+/// 1. create user chain
+/// 2. create protocol liquid token and lst app
+/// 3. list new lst tokens "FOO" and "BAR", fund the vault with "BAR" and send 20 "FOO" to the user
+/// 4. stake 10 "FOO" for protocol lst and swap 10 "FOO" for "BAR"
+/// 5. make the admin the oracle and report a 10% loss of "FOO"
+/// 6. check only the "FOO" backing the pool lowers the pooled native tokens
+#[test_log::test(tokio::test)]
+async fn report_lst_loss() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let user_chain = validator.new_chain().await;
+    let user_account = AccountOwner::from(user_chain.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    let bar_token_id = create_token(&mut stake_chain, "BAR", admin_account, Amount::from_tokens(100)).await;
+    fund_vault(&stake_chain, lst_id, bar_token_id, admin_account, Amount::from_tokens(100)).await;
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: bar_token_id.forget_abi(),
+                    config: LstConfig::new("BAR"),
+                },
+            );
+            block.with_operation(
+                foo_token_id,
+                fungible::Operation::Transfer {
+                    owner: admin_account,
+                    amount: Amount::from_tokens(20),
+                    target_account: fungible::Account {
+                        chain_id: user_chain.id(),
+                        owner: user_account,
+                    },
+                },
+            );
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeLst {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_in: foo_token_id.forget_abi(),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::Swap {
+                    user: user_account,
+                    amount_in: Amount::from_tokens(10),
+                    lst_type_in: foo_token_id.forget_abi(),
+                    lst_type_out: bar_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ConfigureOracle {
+                    oracle: admin_account,
+                    max_reward_bps: 0,
+                    epoch_duration: TimeDelta::from_secs(3600),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::ReportLoss {
+                    token_id: Some(foo_token_id.forget_abi()),
+                    loss_bps: 1_000,
+                    pause: false,
+                },
+            );
+        })
+        .await;
+
+    // the swapped "FOO" is held by the vault but never entered the pool
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { totalPooledNative }").await;
+    let pooled = response["totalPooledNative"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(pooled, Some(Amount::from_tokens(9)));
+}
+
+/////////// Lst swap backing scenario ///////////
+/// 1. create user chain
+/// 2. create protocol liquid token and lst app
+/// 3. list new lst tokens "FOO" and "BAR" and send 10 "FOO" and 10 "BAR" to the user
+/// 4. stake 10 "FOO" for protocol lst and swap 10 "BAR" for the same "FOO"
+/// 5. make the admin the oracle and report a 10% loss of "BAR", then of "FOO"
+/// 6. check the "BAR" now backs the pool instead of the "FOO", which can be delisted
+#[test_log::test(tokio::test)]
+async fn lst_swap_moves_pool_backing() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let user_chain = validator.new_chain().await;
+    let user_account = AccountOwner::from(user_chain.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    let bar_token_id = create_token(&mut stake_chain, "BAR", admin_account, Amount::from_tokens(100)).await;
+    let user = fungible::Account {
+        chain_id: user_chain.id(),
+        owner: user_account,
+    };
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: bar_token_id.forget_abi(),
+                    config: LstConfig::new("BAR"),
+                },
+            );
+            block.with_operation(
+                foo_token_id,
+                fungible::Operation::Transfer {
+                    owner: admin_account,
+                    amount: Amount::from_tokens(10),
+                    target_account: user,
+                },
+            );
+            block.with_operation(
+                bar_token_id,
+                fungible::Operation::Transfer {
+                    owner: admin_account,
+                    amount: Amount::from_tokens(10),
+                    target_account: user,
+                },
+            );
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeLst {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_in: foo_token_id.forget_abi(),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::Swap {
+                    user: user_account,
+                    amount_in: Amount::from_tokens(10),
+                    lst_type_in: bar_token_id.forget_abi(),
+                    lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(foo_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(10)));
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ConfigureOracle {
+                    oracle: admin_account,
+                    max_reward_bps: 0,
+                    epoch_duration: TimeDelta::from_secs(3600),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::ReportLoss {
+                    token_id: Some(bar_token_id.forget_abi()),
+                    loss_bps: 1_000,
+                    pause: false,
+                },
+            );
+        })
+        .await;
+
+    // the "BAR" swapped in backs the pool
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { totalPooledNative }").await;
+    let pooled = response["totalPooledNative"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(pooled, Some(Amount::from_tokens(9)));
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ReportLoss {
+                    token_id: Some(foo_token_id.forget_abi()),
+                    loss_bps: 1_000,
+                    pause: false,
+                },
+            );
+        })
+        .await;
+
+    // the "FOO" paid out no longer does
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { totalPooledNative }").await;
+    let pooled = response["totalPooledNative"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(pooled, Some(Amount::from_tokens(9)));
+
+    // the vault holds no "FOO" anymore, so it can be delisted
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(lst_id, Operation::DelistLst { token_id: foo_token_id.forget_abi() });
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { lstConfigs { keys } }").await;
+    assert_eq!(response["lstConfigs"]["keys"].as_array().map(Vec::len), Some(2));
+}
+
+/////////// Instant unstake scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app with a liquidity buffer of 5 native tokens
+/// 3. stake native for protocol lst, which fills the buffer
+/// 4. instantly unstake 2 protocol lst, using 40% of the buffer
+/// 5. check the native tokens are paid out right away minus a 4% fee
+#[test_log::test(tokio::test)]
+async fn instant_unstake_protocol_lst() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(3600),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ConfigureInstantUnstake {
+                    config: InstantUnstakeConfig {
+                        buffer_target: Amount::from_tokens(5),
+                        min_fee_bps: 0,
+                        max_fee_bps: 1_000,
+                    },
+                },
+            );
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { liquidityBuffer }").await;
+    let buffer = response["liquidityBuffer"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(buffer, Some(Amount::from_tokens(5)));
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::InstantUnstake {
+                    owner: user_account,
+                    amount: Amount::from_tokens(2),
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    // 990 left after staking, plus 2 native tokens minus the 4% fee
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_millis(991_920)));
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(8)));
+}
+
+/////////// Short buffer scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app with a liquidity buffer of 5 native tokens
+/// 3. stake 10 native for protocol lst, which fills the buffer
+/// 4. instantly unstake 6 protocol lst, more than the buffer holds
+/// 5. check the unstake is queued for withdrawal and the buffer shrinks to the 4 native tokens left
+/// 6. instantly unstake the last 4 protocol lst and check they are paid out minus the 10% fee
+#[test_log::test(tokio::test)]
+async fn instant_unstake_falls_back_to_queue() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(3600),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ConfigureInstantUnstake {
+                    config: InstantUnstakeConfig {
+                        buffer_target: Amount::from_tokens(5),
+                        min_fee_bps: 0,
+                        max_fee_bps: 1_000,
+                    },
+                },
+            );
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::InstantUnstake {
+                    owner: user_account,
+                    amount: Amount::from_tokens(6),
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+
+    // the buffer can't cover it, so the native tokens are queued for withdrawal
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(990)));
+    let query = format!("query {{ withdrawalRequests {{ entry(key: {}) {{ value {{ id amount }} }} }} }}", user_account.to_value());
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, query).await;
+    let requests = response
+        .pointer("/withdrawalRequests/entry/value")
+        .and_then(|value| value.as_array())
+        .expect("Missing withdrawal requests");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["amount"].as_str().map(|amount| amount.parse::<Amount>().unwrap()), Some(Amount::from_tokens(6)));
+
+    // only the native tokens left in the vault can back the buffer
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { liquidityBuffer nativeLiquidity }").await;
+    let buffer = response["liquidityBuffer"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(buffer, Some(Amount::from_tokens(4)));
+    let liquidity = response["nativeLiquidity"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(liquidity, Some(Amount::from_tokens(4)));
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::InstantUnstake {
+                    owner: user_account,
+                    amount: Amount::from_tokens(4),
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    // the whole buffer is used up, so the fee is the maximum 10%
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_millis(993_600)));
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, None);
+}
+
+/////////// Unbacked unstake scenario ///////////
+/// 1. create user chain
+/// 2. create protocol liquid token and lst app
+/// 3. list new lst token "FOO" and send 10 to the user
+/// 4. stake 10 "FOO" to get protocol lst, which brings no native tokens into the vault
+/// 5. unstake 4 protocol lst, which the stake chain can't pay out and rejects
+/// 6. check the protocol lst is refunded to the user and the pool is unchanged
+#[test_log::test(tokio::test)]
+async fn unstake_without_native_liquidity_is_refunded() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let user_chain = validator.new_chain().await;
+    let user_account = AccountOwner::from(user_chain.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+    set_minter(&stake_chain, protocol_lst_id, lst_id).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+            block.with_operation(
+                foo_token_id,
+                fungible::Operation::Transfer {
+                    owner: admin_account,
+                    amount: Amount::from_tokens(10),
+                    target_account: fungible::Account {
+                        chain_id: user_chain.id(),
+                        owner: user_account,
+                    },
+                },
+            );
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeLst {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_in: foo_token_id.forget_abi(),
+                },
+            );
+        })
+        .await;
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(10)));
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { nativeLiquidity }").await;
+    assert_eq!(response["nativeLiquidity"].as_str().map(|amount| amount.parse::<Amount>().unwrap()), Some(Amount::ZERO));
+
+    let unstake_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::Unstake {
+                    owner: user_account,
+                    amount: Amount::from_tokens(4),
+                },
+            );
+        })
+        .await;
+
+    // the vault holds no native tokens, so the stake chain rejects the request
+    stake_chain
+        .add_block(move |block| {
+            block.with_messages_from_by_medium(&unstake_cert, &Medium::Direct, MessageAction::Reject);
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(10)));
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { totalPooledNative totalShares }").await;
+    let pooled = response["totalPooledNative"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(pooled, Some(Amount::from_tokens(10)));
+    let shares = response["totalShares"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(shares, Some(Amount::from_tokens(10)));
+}
+
+/////////// Lst registry scenario ///////////
+/// 1. create protocol liquid token and lst app
+/// 2. list new lst token "FOO" with native staking disabled
+/// 3. check its configuration is queryable
+/// 4. delist "FOO" and check only the protocol lst is left
+#[test_log::test(tokio::test)]
+async fn list_and_delist_lst() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig {
+                        native_staking: false,
+                        ..LstConfig::new("FOO")
+                    },
+                },
+            );
+        })
+        .await;
+
+    let query = format!(
+        "query {{ lstConfigs {{ entry(key: {}) {{ value {{ ticker nativeStaking enabled }} }} }} }}",
+        foo_token_id.forget_abi().to_value()
+    );
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, query.as_str()).await;
+    let config = &response["lstConfigs"]["entry"]["value"];
+    assert_eq!(config["ticker"].as_str(), Some("FOO"));
+    assert_eq!(config["nativeStaking"].as_bool(), Some(false));
+    assert_eq!(config["enabled"].as_bool(), Some(true));
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(lst_id, Operation::DelistLst { token_id: foo_token_id.forget_abi() });
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { lstConfigs { keys } }").await;
+    assert_eq!(response["lstConfigs"]["keys"].as_array().map(Vec::len), Some(1));
+}
+
+/////////// Funded lst delist scenario ///////////
+/// 1. create protocol liquid token and lst app
+/// 2. create new lst token "FOO", add it and provide liquidity in it
+/// 3. try to delist "FOO"
+/// 4. check "FOO" is still listed and its reserves are unchanged
+#[test_log::test(tokio::test)]
+async fn delist_lst_with_funds_is_rejected() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+        })
+        .await;
+    fund_vault(&stake_chain, lst_id, foo_token_id, admin_account, Amount::from_tokens(50)).await;
+
+    // the operation is answered with `LstError::LstHasFunds` and changes nothing
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(lst_id, Operation::DelistLst { token_id: foo_token_id.forget_abi() });
+        })
+        .await;
+
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { lstConfigs { keys } }").await;
+    assert_eq!(response["lstConfigs"]["keys"].as_array().map(Vec::len), Some(2));
+    let query = format!("query {{ reserves {{ entry(key: {}) {{ value }} }} }}", foo_token_id.forget_abi().to_value());
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, query).await;
+    let reserve = response
+        .pointer("/reserves/entry/value")
+        .and_then(|value| value.as_str())
+        .map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(reserve, Some(Amount::from_tokens(50)));
+}
+
+/////////// Native stake slippage scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. create new lst token "FOO", transfer it to the lst app vault and add it with a 2:1 rate
+/// 4. stake native asking for more "FOO" than the rate gives
+/// 5. check the native tokens are refunded and no "FOO" is sent
+#[test_log::test(tokio::test)]
+async fn native_stake_below_min_amount_out_is_refunded() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+    fund_vault(&stake_chain, lst_id, foo_token_id, admin_account, Amount::from_tokens(100)).await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: foo_token_id.forget_abi(),
+                    config: LstConfig::new("FOO"),
+                },
+            );
+            block.with_operation(
+                lst_id,
+                Operation::UpdateExchangeRate {
+                    token_id: foo_token_id.forget_abi(),
+                    rate: Amount::from_tokens(2),
+                },
+            );
+        })
+        .await;
+
+    // 10 native only buys 5 "FOO"
+    let stake_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::from_tokens(6),
+                },
+            );
+        })
+        .await;
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(990)));
+
+    let stake_msg = stake_chain
+        .add_block(|block| {
+            block.with_messages_from(&stake_cert);
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_messages_from(&stake_msg);
+        })
+        .await;
+
+    let user_balance = fungible::query_account(foo_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, None);
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(1000)));
+
+    let app_foo_balance = fungible::query_account(foo_token_id, &stake_chain, AccountOwner::from(lst_id)).await;
+    assert_eq!(app_foo_balance, Some(Amount::from_tokens(100)));
+}
+
+/////////// Rejected native stake scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. create new lst token "FOO" without adding it to the lst app
+/// 4. stake native to get "FOO", stake chain rejects the request
+/// 5. check the native tokens are refunded to the user
+#[test_log::test(tokio::test)]
+async fn rejected_native_stake_is_refunded() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let foo_token_id = create_token(&mut stake_chain, "FOO", admin_account, Amount::from_tokens(100)).await;
+
+    let stake_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: foo_token_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(990)));
+
+    // "FOO" is not approved, so the stake chain rejects the request
+    stake_chain
+        .add_block(move |block| {
+            block.with_messages_from_by_medium(&stake_cert, &Medium::Direct, MessageAction::Reject);
+        })
+        .await;
+
+    let app_native_balance = stake_chain.owner_balance(&lst_id.application_description_hash.into()).await;
+    assert_eq!(app_native_balance, None);
+
+    user_chain.handle_received_messages().await;
+
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(1000)));
+    let app_native_balance = user_chain.owner_balance(&lst_id.application_description_hash.into()).await;
+    assert_eq!(app_native_balance, None);
+}
+
+/////////// Rejected lst stake scenario ///////////
+/// 1. create user chain
+/// 2. create protocol liquid token and lst app
+/// 3. create new lst token "BAR" without adding it to the lst app and send some to the user
+/// 4. stake "BAR" to get protocol lst, stake chain rejects the request
+/// 5. check the "BAR" tokens are refunded to the user
+#[test_log::test(tokio::test)]
+async fn rejected_lst_stake_is_refunded() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let user_chain = validator.new_chain().await;
+    let user_account = AccountOwner::from(user_chain.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    // send "BAR" to the user
+    let bar_token_id = create_token(&mut stake_chain, "BAR", admin_account, Amount::from_tokens(100)).await;
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                bar_token_id,
+                fungible::Operation::Transfer {
+                    owner: admin_account,
+                    amount: Amount::from_tokens(20),
+                    target_account: fungible::Account {
+                        chain_id: user_chain.id(),
+                        owner: user_account,
+                    },
+                },
+            );
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(bar_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(20)));
+
+    let stake_cert = user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeLst {
+                    user: user_account,
+                    amount: Amount::from_tokens(20),
+                    lst_type_in: bar_token_id.forget_abi(),
+                },
+            );
+        })
+        .await;
+
+    let user_balance = fungible::query_account(bar_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, None);
+
+    // "BAR" is not approved, so the stake chain rejects the request
+    stake_chain
+        .add_block(move |block| {
+            block.with_messages_from_by_medium(&stake_cert, &Medium::Direct, MessageAction::Reject);
+        })
+        .await;
+
+    let app_bar_balance = fungible::query_account(bar_token_id, &stake_chain, AccountOwner::from(lst_id)).await;
+    assert_eq!(app_bar_balance, None);
+
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(bar_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(20)));
+    let app_bar_balance = fungible::query_account(bar_token_id, &user_chain, AccountOwner::from(lst_id)).await;
+    assert_eq!(app_bar_balance, None);
+}
+
+/////////// Deposit cap scenario ///////////
+/// 1. create user chain
+/// 2. create protocol liquid token and lst app
+/// 3. create new lst token "BAR", add it with a deposit cap of 10 and send 20 to the user
+/// 4. stake 20 "BAR" to get protocol lst
+/// 5. check the "BAR" tokens are refunded to the user and the remaining capacity is unchanged
+#[test_log::test(tokio::test)]
+async fn lst_stake_over_deposit_cap_is_refunded() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let user_chain = validator.new_chain().await;
+    let user_account = AccountOwner::from(user_chain.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let bar_token_id = create_token(&mut stake_chain, "BAR", admin_account, Amount::from_tokens(100)).await;
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::NewLst {
+                    token_id: bar_token_id.forget_abi(),
+                    config: LstConfig {
+                        deposit_cap: Some(Amount::from_tokens(10)),
+                        ..LstConfig::new("BAR")
+                    },
+                },
+            );
+            block.with_operation(
+                bar_token_id,
+                fungible::Operation::Transfer {
+                    owner: admin_account,
+                    amount: Amount::from_tokens(20),
+                    target_account: fungible::Account {
+                        chain_id: user_chain.id(),
+                        owner: user_account,
+                    },
+                },
+            );
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeLst {
+                    user: user_account,
+                    amount: Amount::from_tokens(20),
+                    lst_type_in: bar_token_id.forget_abi(),
+                },
+            );
+        })
+        .await;
+
+    stake_chain.handle_received_messages().await;
+    user_chain.handle_received_messages().await;
+
+    let user_balance = fungible::query_account(bar_token_id, &user_chain, user_account).await;
+    assert_eq!(user_balance, Some(Amount::from_tokens(20)));
+    let user_protocol_lst_balance = fungible::query_account(protocol_lst_id, &user_chain, user_account).await;
+    assert_eq!(user_protocol_lst_balance, None);
+
+    let query = format!("query {{ remainingDepositCapacity(tokenId: {}) }}", bar_token_id.forget_abi().to_value());
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, query.as_str()).await;
+    let remaining = response["remainingDepositCapacity"].as_str().map(|amount| amount.parse::<Amount>().unwrap());
+    assert_eq!(remaining, Some(Amount::from_tokens(10)));
+}
+
+/////////// Paused stake scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. pause staking globally and forward the flags to the user chain
+/// 4. stake native for protocol lst
+/// 5. check the stake is rejected on the user chain and no native tokens moved
+#[test_log::test(tokio::test)]
+async fn paused_stake_is_rejected() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::SetPaused {
+                    token_id: None,
+                    flags: PauseFlags {
+                        stake: true,
+                        swap: false,
+                        unstake: false,
+                    },
+                    chains: vec![user_chain.id()],
+                },
+            );
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+
+    let QueryOutcome { response, .. } = user_chain.graphql_query(lst_id, "query { paused { stake swap } }").await;
+    assert_eq!(response.pointer("/paused/stake").and_then(|paused| paused.as_bool()), Some(true));
+    assert_eq!(response.pointer("/paused/swap").and_then(|paused| paused.as_bool()), Some(false));
+
+    // the operation is answered with `LstError::Paused` before any funds move
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: user_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(Amount::from_tokens(1000)));
+    let QueryOutcome { response, .. } = stake_chain.graphql_query(lst_id, "query { totalShares }").await;
+    assert_eq!(response["totalShares"].as_str().map(|amount| amount.parse::<Amount>().unwrap()), Some(Amount::ZERO));
+}
+
+/////////// Unauthorized stake scenario ///////////
+/// 1. create user chain and fund it with native tokens
+/// 2. create protocol liquid token and lst app
+/// 3. move some native tokens to another owner on the user chain
+/// 4. the chain owner stakes native tokens on behalf of the other owner
+/// 5. check the stake is rejected and no native tokens moved
+#[test_log::test(tokio::test)]
+async fn stake_for_other_user_is_rejected() {
+    let validator = TestValidator::new().await;
+    let mut stake_chain = validator.new_chain().await;
+    let admin_account = AccountOwner::from(stake_chain.public_key());
+    let (user_chain, user_account) = create_funded_user_chain(&validator, Amount::from_tokens(1000)).await;
+    let other_account = AccountOwner::from(validator.new_chain().await.public_key());
+
+    let protocol_lst_id = create_token(&mut stake_chain, "PLST", admin_account, Amount::from_tokens(100)).await;
+    let lst_module_id = stake_chain.publish_current_module::<LstAbi, Parameters, InstantiationArgument>().await;
+    let stake_parameter = Parameters { protocol_lst: protocol_lst_id };
+    let instantiation_argument = InstantiationArgument {
+        admin: admin_account,
+        withdrawal_cooldown: TimeDelta::from_secs(0),
+    };
+    let lst_id = stake_chain.create_application(lst_module_id, stake_parameter, instantiation_argument, vec![]).await;
+
+    let recipient_other = Recipient::Account(Account::new(user_chain.id(), other_account));
+    user_chain
+        .add_block(|block| {
+            block.with_native_token_transfer(user_account, recipient_other, Amount::from_tokens(100));
+        })
+        .await;
+    user_chain.handle_received_messages().await;
+    assert_eq!(user_chain.owner_balance(&other_account).await, Some(Amount::from_tokens(100)));
+
+    // the operation is answered with `LstError::Unauthorized` before any funds move
+    user_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::StakeNative {
+                    user: other_account,
+                    amount: Amount::from_tokens(10),
+                    lst_type_out: protocol_lst_id.forget_abi(),
+                    min_amount_out: Amount::ZERO,
+                },
+            );
+        })
+        .await;
+
+    assert_eq!(user_chain.owner_balance(&other_account).await, Some(Amount::from_tokens(100)));
+    let app_native_balance = user_chain.owner_balance(&lst_id.application_description_hash.into()).await;
+    assert_eq!(app_native_balance, None);
+}
+
+/// Creates a new chain and funds its owner with `amount` native tokens from the root chain.
+async fn create_funded_user_chain(validator: &TestValidator, amount: Amount) -> (ActiveChain, AccountOwner) {
+    // chain which is able to mint native token
+    let funding_chain = validator.get_chain(&ChainId::root(0));
+
+    let user_chain = validator.new_chain().await;
+    let user_account = AccountOwner::from(user_chain.public_key());
+    let recipient_user = Recipient::Account(Account::new(user_chain.id(), user_account));
+    let transfer_certificate = funding_chain
+        .add_block(|block| {
+            block.with_native_token_transfer(AccountOwner::CHAIN, recipient_user, amount);
+        })
+        .await;
+
+    user_chain
+        .add_block(|block| {
+            block.with_messages_from(&transfer_certificate);
+        })
+        .await;
+
+    assert_eq!(user_chain.owner_balance(&user_account).await, Some(amount));
+    (user_chain, user_account)
+}
+
+/// Creates a fungible token with `ticker` where `owner` holds the whole `amount` on `chain`.
+async fn create_token(chain: &mut ActiveChain, ticker: &str, owner: AccountOwner, amount: Amount) -> ApplicationId<fungible::FungibleTokenAbi> {
+    let module_id = chain
+        .publish_bytecode_files_in::<fungible::FungibleTokenAbi, fungible::Parameters, fungible::InitialState>("../fungible")
+        .await;
+    let initial_state = fungible::InitialStateBuilder::default().with_account(owner, amount);
+    // mintable, so that the token can serve as the protocol lst
+    let params = fungible::Parameters {
+        mintable: true,
+        ..fungible::Parameters::new(ticker)
+    };
+    let token_id = chain.create_application(module_id, params, initial_state.build(), vec![]).await;
+
+    assert_eq!(fungible::query_account(token_id, chain, owner).await, Some(amount));
+    token_id
+}
+
+/// Provides `amount` of `token_id` from `owner` as liquidity to the lst app vault on the stake chain.
+async fn fund_vault(stake_chain: &ActiveChain, lst_id: ApplicationId<LstAbi>, token_id: ApplicationId<fungible::FungibleTokenAbi>, owner: AccountOwner, amount: Amount) {
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(
+                lst_id,
+                Operation::ProvideLiquidity {
+                    owner,
+                    token_id: token_id.forget_abi(),
+                    amount,
+                },
+            );
+        })
+        .await;
+}
+
+/// Makes the lst app the minter of the protocol lst on the stake chain.
+async fn set_minter(stake_chain: &ActiveChain, protocol_lst_id: ApplicationId<fungible::FungibleTokenAbi>, lst_id: ApplicationId<LstAbi>) {
+    stake_chain
+        .add_block(|block| {
+            block.with_operation(protocol_lst_id, fungible::Operation::SetMinter { minter: AccountOwner::from(lst_id) });
+        })
+        .await;
 }